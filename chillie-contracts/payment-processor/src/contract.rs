@@ -3,21 +3,45 @@
 
 #![cfg_attr(target_arch = "wasm32", no_main)]
 
-use super::state;
+mod state;
 
-use crate::{
-    PaymentProcessorAbi, PaymentOperation, PaymentResult, PaymentError,
-    RoomId, UserId, TipId, Timestamp, Hash, PendingTransaction, PendingTip, PendingAccessFee,
-    RoomPaymentSettings, UserPaymentPreferences, StreamQuality, PaymentStats,
+use chillie_payment_processor::{
+    PaymentProcessorAbi, PaymentOperation, PaymentResult, PaymentError, PaymentMessage, PaymentParameters,
+    RoomId, UserId, TipId, Timestamp, Hash, PendingTransaction, PendingTip, PendingAccessFee, PendingReplayFee, BlobId,
+    RoomLifecycle, RoomPaymentPool, RoomPaymentSettings, UserPaymentState, StreamQuality,
+    PaymentStats, SettlementRecord,
 };
 use linera_sdk::{
-    linera_base_types::WithContractAbi,
+    abis::fungible::{FungibleOperation, FungibleTokenAbi},
+    linera_base_types::{Account, AccountOwner, Amount, BcsHashable, ChainId, CryptoHash, WithContractAbi},
     views::{View, ViewStorageContext, RootView},
     Contract, ContractRuntime,
 };
 
 use self::state::PaymentProcessorState;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Transactions a user settles together; their hash identifies the settlement
+#[derive(Serialize, Deserialize)]
+struct SettlementBatch {
+    user_id: UserId,
+    transactions: Vec<PendingTransaction>,
+    timestamp: Timestamp,
+}
+
+impl BcsHashable<'_> for SettlementBatch {}
+
+/// Payout of a host's revenue; its hash identifies the withdrawal
+#[derive(Serialize, Deserialize)]
+struct Withdrawal {
+    room_id: RoomId,
+    amount: Amount,
+    destination: Account,
+    timestamp: Timestamp,
+}
+
+impl BcsHashable<'_> for Withdrawal {}
 
 pub struct PaymentProcessorContract {
     state: PaymentProcessorState,
@@ -31,9 +55,9 @@ impl WithContractAbi for PaymentProcessorContract {
 }
 
 impl Contract for PaymentProcessorContract {
-    type Message = ();
+    type Message = PaymentMessage;
     type InstantiationArgument = ();
-    type Parameters = ();
+    type Parameters = PaymentParameters;
     type EventValue = ();
 
     async fn load(runtime: ContractRuntime<Self>) -> Self {
@@ -45,7 +69,8 @@ impl Contract for PaymentProcessorContract {
 
     async fn instantiate(&mut self, _argument: ()) {
         // Validate that the application parameters were configured correctly.
-        self.runtime.application_parameters();
+        let parameters = self.runtime.application_parameters();
+        assert!(parameters.fee_schedule.is_valid(), "Fees must not exceed 100% of settled revenue");
 
         // Initialize with default state
         let default_stats = PaymentStats::default();
@@ -54,26 +79,61 @@ impl Contract for PaymentProcessorContract {
 
     async fn execute_operation(&mut self, operation: PaymentOperation) -> PaymentResult {
         match operation {
+            PaymentOperation::Deposit { amount } => {
+                self.deposit(amount).await
+            }
+            PaymentOperation::WithdrawBalance { amount } => {
+                self.withdraw_balance(amount).await
+            }
             PaymentOperation::SendTip { room_id, amount, message, super_chat } => {
-                self.process_tip(room_id, amount, message, super_chat)
+                self.process_tip(room_id, amount, message, super_chat).await
             }
             PaymentOperation::PayAccessFee { room_id, quality_tier } => {
-                self.process_access_fee(room_id, quality_tier)
+                self.process_access_fee(room_id, quality_tier).await
             }
             PaymentOperation::SettlePendingTransactions { user_id } => {
-                self.settle_user_transactions(user_id)
+                // SECURITY FIX: Only the payer may settle their own pending transactions
+                if self.runtime.authenticated_signer().map(|signer| signer.to_string()) != Some(user_id.clone()) {
+                    return PaymentResult::PaymentError { error: PaymentError::Unauthorized };
+                }
+                self.settle_user_transactions(user_id).await
+            }
+            PaymentOperation::CreatePaymentPool { room_id, settings } => {
+                self.create_payment_pool(room_id, settings).await
+            }
+            PaymentOperation::ClosePaymentPool { room_id } => {
+                self.close_payment_pool(room_id).await
             }
             PaymentOperation::UpdateRoomSettings { room_id, settings } => {
-                self.update_room_settings(room_id, settings)
+                self.update_room_settings(room_id, settings).await
+            }
+            PaymentOperation::WithdrawFunds { room_id, amount, destination } => {
+                self.withdraw_funds(room_id, amount, destination).await
+            }
+            PaymentOperation::PayReplayAccess { room_id, blob_id } => {
+                self.process_replay_fee(room_id, blob_id).await
             }
-            PaymentOperation::WithdrawFunds { room_id, amount } => {
-                self.withdraw_funds(room_id, amount)
+            PaymentOperation::ApplyRoomLifecycle { event } => {
+                self.receive_room_lifecycle(event).await
             }
         }
     }
 
-    async fn execute_message(&mut self, _message: ()) {
-        panic!("Payment processor doesn't support any cross-chain messages");
+    async fn execute_message(&mut self, message: PaymentMessage) {
+        // Lifecycle changes are only applied on the pool chain; a rejected one must not open or
+        // close pools on the chain that forwarded it
+        if self.runtime.message_is_bouncing() == Some(true) {
+            return;
+        }
+
+        let Some(origin) = self.runtime.message_origin_chain_id() else {
+            return;
+        };
+        match message {
+            PaymentMessage::RoomLifecycle(event) => {
+                self.apply_room_lifecycle(event, origin).await;
+            }
+        }
     }
 
     async fn store(mut self) {
@@ -82,10 +142,85 @@ impl Contract for PaymentProcessorContract {
 }
 
 impl PaymentProcessorContract {
+    /// Credit tokens transferred from the signer's account to their payment balance
+    ///
+    /// The first deposit opens the user's payment state with default preferences.
+    async fn deposit(&mut self, amount: Amount) -> PaymentResult {
+        let owner = match self.runtime.authenticated_signer() {
+            Some(signer) => signer,
+            None => return PaymentResult::PaymentError { error: PaymentError::Unauthorized },
+        };
+        let user_id = owner.to_string();
+
+        if amount == Amount::ZERO {
+            return PaymentResult::PaymentError { error: PaymentError::InvalidAmount };
+        }
+
+        let existing = self.state.get_user_state(&user_id).await.expect("Failed to get user");
+        let is_new_user = existing.is_none();
+        let mut user_state = existing.unwrap_or_else(|| UserPaymentState::new(user_id.clone()));
+
+        user_state.balance = match user_state.balance.try_add(amount) {
+            Ok(balance) => balance,
+            Err(_) => return PaymentResult::PaymentError { error: PaymentError::InvalidAmount },
+        };
+
+        self.receive_tokens(owner, amount);
+
+        let balance = user_state.balance;
+        self.state.insert_user(user_id.clone(), user_state).await.expect("Failed to update user");
+
+        if is_new_user {
+            let mut stats = self.state.get_global_stats().clone();
+            stats.active_users += 1;
+            self.state.update_stats(stats);
+        }
+
+        PaymentResult::Deposited { user_id, balance }
+    }
+
+    /// Pay tokens from the signer's payment balance back to their account on this chain
+    async fn withdraw_balance(&mut self, amount: Amount) -> PaymentResult {
+        let owner = match self.runtime.authenticated_signer() {
+            Some(signer) => signer,
+            None => return PaymentResult::PaymentError { error: PaymentError::Unauthorized },
+        };
+        let user_id = owner.to_string();
+
+        if amount == Amount::ZERO {
+            return PaymentResult::PaymentError { error: PaymentError::InvalidAmount };
+        }
+
+        let Some(mut user_state) = self.state.get_user_state(&user_id).await.expect("Failed to get user") else {
+            return PaymentResult::PaymentError { error: PaymentError::UserNotFound };
+        };
+        // Tips and fees are deducted when they are made, so pending ones cannot be withdrawn
+        user_state.balance = match user_state.balance.try_sub(amount) {
+            Ok(balance) => balance,
+            Err(_) => return PaymentResult::PaymentError { error: PaymentError::InsufficientBalance },
+        };
+        let balance = user_state.balance;
+        self.state.insert_user(user_id.clone(), user_state).await.expect("Failed to update user");
+
+        let destination = Account {
+            chain_id: self.runtime.chain_id(),
+            owner,
+        };
+        self.send_tokens(destination, amount);
+
+        PaymentResult::BalanceWithdrawn { user_id, balance }
+    }
+
     /// Process a tip to a room
-    fn process_tip(&mut self, room_id: RoomId, amount: u64, message: Option<String>, super_chat: bool) -> PaymentResult {
+    async fn process_tip(
+        &mut self,
+        room_id: RoomId,
+        amount: Amount,
+        message: Option<String>,
+        super_chat: bool,
+    ) -> PaymentResult {
         // SECURITY FIX: Validate input parameters
-        if amount == 0 {
+        if amount == Amount::ZERO {
             return PaymentResult::PaymentError { error: PaymentError::InvalidAmount };
         }
 
@@ -96,7 +231,7 @@ impl PaymentProcessorContract {
         };
 
         // SECURITY FIX: Use proper timestamp
-        let timestamp = self.runtime.system_time().micros_since_epoch();
+        let timestamp = self.runtime.system_time().micros();
 
         // Validate message length to prevent DoS
         if let Some(ref msg) = message {
@@ -106,17 +241,18 @@ impl PaymentProcessorContract {
         }
 
         // SECURITY FIX: Check user balance before processing tip
-        let user_balance = match self.state.get_user_state(&user_id) {
+        let user_balance = match self.state.get_user_state(&user_id).await.expect("Failed to get user") {
             Some(user_state) => user_state.balance,
             None => return PaymentResult::PaymentError { error: PaymentError::UserNotFound },
         };
 
-        if user_balance < amount as u128 {
+        if user_balance < amount {
             return PaymentResult::PaymentError { error: PaymentError::InsufficientBalance };
         }
 
         // Create tip
         let tip = PendingTip {
+            room_id: room_id.clone(),
             from: user_id.clone(),
             amount,
             message,
@@ -127,32 +263,37 @@ impl PaymentProcessorContract {
         let tip_id = format!("tip-{}-{}-{}", room_id, timestamp, amount);
 
         // Add to room's pending tips
-        if let Some(mut room) = self.state.get_room_stats(&room_id).cloned() {
+        if let Some(mut room) = self.state.get_room_stats(&room_id).await.expect("Failed to get room") {
+            if !room.accepts_payments() {
+                let error = if room.is_open { PaymentError::PaymentsDisabled } else { PaymentError::RoomClosed };
+                return PaymentResult::PaymentError { error };
+            }
+
             // SECURITY FIX: Add overflow protection
-            match room.total_tips.checked_add(amount as u128) {
-                Some(new_total) => room.total_tips = new_total,
-                None => return PaymentResult::PaymentError { error: PaymentError::InvalidAmount },
+            match room.total_tips.try_add(amount) {
+                Ok(new_total) => room.total_tips = new_total,
+                Err(_) => return PaymentResult::PaymentError { error: PaymentError::InvalidAmount },
             }
 
             room.pending_tips.push(tip.clone());
-            self.state.insert_room(room_id.clone(), room);
+            self.state.insert_room(room_id.clone(), room).await.expect("Failed to update room");
         } else {
             return PaymentResult::PaymentError { error: PaymentError::RoomNotFound };
         }
 
         // Update user's pending transactions
-        if let Some(mut user_state) = self.state.get_user_state(&user_id).cloned() {
+        if let Some(mut user_state) = self.state.get_user_state(&user_id).await.expect("Failed to get user") {
             // SECURITY FIX: Safe balance subtraction
-            user_state.balance = user_state.balance.saturating_sub(amount as u128);
+            user_state.balance = user_state.balance.saturating_sub(amount);
             user_state.pending_transactions.push(PendingTransaction::Tip(tip.clone()));
-            self.state.insert_user(user_id.clone(), user_state);
+            self.state.insert_user(user_id.clone(), user_state).await.expect("Failed to update user");
         } else {
             return PaymentResult::PaymentError { error: PaymentError::UserNotFound };
         }
 
         // Check if user should auto-settle
-        if self.should_auto_settle(&user_id) {
-            self.settle_user_transactions(user_id);
+        if self.should_auto_settle(&user_id).await {
+            self.settle_user_transactions(user_id).await;
         }
 
         PaymentResult::TipSent {
@@ -163,7 +304,7 @@ impl PaymentProcessorContract {
     }
 
     /// Process an access fee payment
-    fn process_access_fee(&mut self, room_id: RoomId, quality_tier: StreamQuality) -> PaymentResult {
+    async fn process_access_fee(&mut self, room_id: RoomId, quality_tier: StreamQuality) -> PaymentResult {
         // SECURITY FIX: Proper authentication
         let user_id = match self.runtime.authenticated_signer() {
             Some(signer) => signer.to_string(),
@@ -171,14 +312,15 @@ impl PaymentProcessorContract {
         };
 
         // Get room to find pricing for quality tier
-        let room = match self.state.get_room_stats(&room_id).cloned() {
+        let room = match self.state.get_room_stats(&room_id).await.expect("Failed to get room") {
             Some(room) => room,
             None => return PaymentResult::PaymentError { error: PaymentError::RoomNotFound },
         };
 
         // SECURITY FIX: Check if payments are enabled for the room
-        if !room.settings.payments_enabled {
-            return PaymentResult::PaymentError { error: PaymentError::PaymentsDisabled };
+        if !room.accepts_payments() {
+            let error = if room.is_open { PaymentError::PaymentsDisabled } else { PaymentError::RoomClosed };
+            return PaymentResult::PaymentError { error };
         }
 
         let amount = match room.settings.quality_tiers.get(&quality_tier) {
@@ -187,19 +329,20 @@ impl PaymentProcessorContract {
         };
 
         // SECURITY FIX: Use proper timestamp
-        let timestamp = self.runtime.system_time().micros_since_epoch();
+        let timestamp = self.runtime.system_time().micros();
 
         // SECURITY FIX: Check user balance before processing
-        let user_balance = match self.state.get_user_state(&user_id) {
+        let user_balance = match self.state.get_user_state(&user_id).await.expect("Failed to get user") {
             Some(user_state) => user_state.balance,
             None => return PaymentResult::PaymentError { error: PaymentError::UserNotFound },
         };
 
-        if user_balance < amount as u128 {
+        if user_balance < amount {
             return PaymentResult::PaymentError { error: PaymentError::InsufficientBalance };
         }
 
         let access_fee = PendingAccessFee {
+            room_id: room_id.clone(),
             user_id: user_id.clone(),
             amount,
             quality_tier,
@@ -207,22 +350,22 @@ impl PaymentProcessorContract {
         };
 
         // Add to room's pending access fees
-        if let Some(mut room) = self.state.get_room_stats(&room_id).cloned() {
+        if let Some(mut room) = self.state.get_room_stats(&room_id).await.expect("Failed to get room") {
             // SECURITY FIX: Add overflow protection
-            match room.total_access_fees.checked_add(amount as u128) {
-                Some(new_total) => room.total_access_fees = new_total,
-                None => return PaymentResult::PaymentError { error: PaymentError::InvalidAmount },
+            match room.total_access_fees.try_add(amount) {
+                Ok(new_total) => room.total_access_fees = new_total,
+                Err(_) => return PaymentResult::PaymentError { error: PaymentError::InvalidAmount },
             }
 
             room.pending_access_fees.push(access_fee.clone());
-            self.state.insert_room(room_id.clone(), room);
+            self.state.insert_room(room_id.clone(), room).await.expect("Failed to update room");
         }
 
         // Update user's pending transactions
-        if let Some(mut user_state) = self.state.get_user_state(&user_id).cloned() {
+        if let Some(mut user_state) = self.state.get_user_state(&user_id).await.expect("Failed to get user") {
             user_state.pending_transactions.push(PendingTransaction::AccessFee(access_fee.clone()));
-            user_state.balance = user_state.balance.saturating_sub(amount as u128);
-            self.state.insert_user(user_id, user_state);
+            user_state.balance = user_state.balance.saturating_sub(amount);
+            self.state.insert_user(user_id, user_state).await.expect("Failed to update user");
         } else {
             return PaymentResult::PaymentError { error: PaymentError::UserNotFound };
         }
@@ -234,30 +377,93 @@ impl PaymentProcessorContract {
         }
     }
 
-    /// Settle user's pending transactions
-    fn settle_user_transactions(&mut self, user_id: UserId) -> PaymentResult {
-        let user_state = match self.state.get_user_state(&user_id).cloned() {
-            Some(state) => state,
-            None => return PaymentResult::PaymentError { error: PaymentError::UserNotFound },
+    /// Process the purchase of replay access to a room recording
+    ///
+    /// Replays are sold after the live stream, so closed rooms keep accepting them.
+    async fn process_replay_fee(&mut self, room_id: RoomId, blob_id: BlobId) -> PaymentResult {
+        let user_id = match self.runtime.authenticated_signer() {
+            Some(signer) => signer.to_string(),
+            None => return PaymentResult::PaymentError { error: PaymentError::Unauthorized },
         };
 
-        let transaction_count = user_state.pending_transactions.len();
-        let total_amount: u128 = user_state.pending_transactions.iter()
-            .map(|tx| match tx {
-                PendingTransaction::Tip(tip) => tip.amount as u128,
-                PendingTransaction::AccessFee(fee) => fee.amount as u128,
-            })
-            .sum();
+        let Some(mut room) = self.state.get_room_stats(&room_id).await.expect("Failed to get room") else {
+            return PaymentResult::PaymentError { error: PaymentError::RoomNotFound };
+        };
+        let Some(amount) = room.replay_prices.get(&blob_id).copied() else {
+            return PaymentResult::PaymentError { error: PaymentError::RecordingNotFound };
+        };
 
-        // Create settlement hash (simplified)
-        let settlement_hash = [0u8; 32]; // In real implementation, create actual hash
+        let access_key = (room_id.clone(), blob_id.clone(), user_id.clone());
+        if self.state.replay_access.contains_key(&access_key).await.expect("Failed to get replay access") {
+            return PaymentResult::ReplayAccessPaid { room_id, blob_id, pending_settlement: false };
+        }
 
-        // Clear user's pending transactions
-        if let Some(mut user_state) = self.state.get_user_state(&user_id).cloned() {
-            user_state.pending_transactions.clear();
-            self.state.insert_user(user_id.clone(), user_state);
+        let Some(mut user_state) = self.state.get_user_state(&user_id).await.expect("Failed to get user") else {
+            return PaymentResult::PaymentError { error: PaymentError::UserNotFound };
+        };
+        if user_state.balance < amount {
+            return PaymentResult::PaymentError { error: PaymentError::InsufficientBalance };
         }
 
+        let timestamp = self.runtime.system_time().micros();
+        let replay_fee = PendingReplayFee {
+            room_id: room_id.clone(),
+            user_id: user_id.clone(),
+            blob_id: blob_id.clone(),
+            amount,
+            timestamp,
+        };
+
+        // Replay revenue counts towards the room's access fees
+        match room.total_access_fees.try_add(amount) {
+            Ok(new_total) => room.total_access_fees = new_total,
+            Err(_) => return PaymentResult::PaymentError { error: PaymentError::InvalidAmount },
+        }
+        room.pending_replay_fees.push(replay_fee.clone());
+        self.state.insert_room(room_id.clone(), room).await.expect("Failed to update room");
+
+        user_state.balance = user_state.balance.saturating_sub(amount);
+        user_state.pending_transactions.push(PendingTransaction::ReplayFee(replay_fee));
+        self.state.insert_user(user_id, user_state).await.expect("Failed to update user");
+
+        self.state.replay_access.insert(&access_key, timestamp).expect("Failed to record replay access");
+
+        PaymentResult::ReplayAccessPaid { room_id, blob_id, pending_settlement: true }
+    }
+
+    /// Settle user's pending transactions, making their amounts available to the rooms' hosts
+    ///
+    /// The user's and the rooms' pending lists are cleared together and the settlement is logged.
+    async fn settle_user_transactions(&mut self, user_id: UserId) -> PaymentResult {
+        let Some(mut user_state) = self.state.get_user_state(&user_id).await.expect("Failed to get user") else {
+            return PaymentResult::PaymentError { error: PaymentError::UserNotFound };
+        };
+
+        let transactions = std::mem::take(&mut user_state.pending_transactions);
+        let transaction_count = transactions.len();
+        let total_amount = transactions.iter()
+            .fold(Amount::ZERO, |total, tx| total.saturating_add(tx.amount()));
+
+        let mut room_ids: Vec<RoomId> = transactions.iter()
+            .map(|tx| tx.room_id().clone())
+            .collect();
+        room_ids.sort();
+        room_ids.dedup();
+
+        // Clear user's pending transactions before relay shares may credit the same user
+        self.state.insert_user(user_id.clone(), user_state).await.expect("Failed to update user");
+
+        // Split the user's side of each room's pending payments between host, platform and relays
+        for room_id in room_ids {
+            if let Some(mut pool) = self.state.get_room_stats(&room_id).await.expect("Failed to get room") {
+                let settled = pool.take_pending_from(&user_id);
+                self.credit_settled(&mut pool, settled).await;
+                self.state.insert_room(room_id, pool).await.expect("Failed to update room");
+            }
+        }
+
+        let settlement_hash = self.record_settlement(&user_id, transactions).await;
+
         PaymentResult::TransactionsSettled {
             user_id,
             transaction_count,
@@ -266,77 +472,675 @@ impl PaymentProcessorContract {
         }
     }
 
+    /// Open a payment pool for a room on a deployment without a room manager
+    async fn create_payment_pool(&mut self, room_id: RoomId, settings: RoomPaymentSettings) -> PaymentResult {
+        let host = match self.runtime.authenticated_signer() {
+            Some(signer) => signer.to_string(),
+            None => return PaymentResult::PaymentError { error: PaymentError::Unauthorized },
+        };
+
+        // Rooms of a room manager get their pools from its lifecycle events
+        if self.runtime.application_parameters().room_manager.is_some() {
+            return PaymentResult::PaymentError { error: PaymentError::ManagedByRoomManager };
+        }
+
+        if room_id.is_empty() || settings.min_tip == Amount::ZERO {
+            return PaymentResult::PaymentError { error: PaymentError::InvalidRoomSettings };
+        }
+
+        if self.state.has_room(&room_id).await.expect("Failed to get room") {
+            return PaymentResult::PaymentError { error: PaymentError::RoomAlreadyExists };
+        }
+
+        let pool = RoomPaymentPool::new(room_id.clone(), host, settings);
+        self.state.insert_room(room_id.clone(), pool).await.expect("Failed to update room");
+        self.count_active_room(true);
+
+        PaymentResult::PaymentPoolCreated { room_id }
+    }
+
+    /// Settle and close a room's payment pool on a deployment without a room manager
+    async fn close_payment_pool(&mut self, room_id: RoomId) -> PaymentResult {
+        let user_id = match self.runtime.authenticated_signer() {
+            Some(signer) => signer.to_string(),
+            None => return PaymentResult::PaymentError { error: PaymentError::Unauthorized },
+        };
+
+        if self.runtime.application_parameters().room_manager.is_some() {
+            return PaymentResult::PaymentError { error: PaymentError::ManagedByRoomManager };
+        }
+
+        let room = match self.state.get_room_stats(&room_id).await.expect("Failed to get room") {
+            Some(room) => room,
+            None => return PaymentResult::PaymentError { error: PaymentError::RoomNotFound },
+        };
+        if room.host != user_id {
+            return PaymentResult::PaymentError { error: PaymentError::Unauthorized };
+        }
+        if !room.is_open {
+            return PaymentResult::PaymentError { error: PaymentError::RoomClosed };
+        }
+
+        let settled_amount = self.close_pool(room).await;
+
+        PaymentResult::PaymentPoolClosed { room_id, settled_amount }
+    }
+
     /// Update room payment settings
-    fn update_room_settings(&mut self, room_id: RoomId, settings: RoomPaymentSettings) -> PaymentResult {
+    async fn update_room_settings(&mut self, room_id: RoomId, settings: RoomPaymentSettings) -> PaymentResult {
         // SECURITY FIX: Check authentication
         let user_id = match self.runtime.authenticated_signer() {
             Some(signer) => signer.to_string(),
             None => return PaymentResult::PaymentError { error: PaymentError::Unauthorized },
         };
 
-        if let Some(mut room) = self.state.get_room_stats(&room_id).cloned() {
+        if let Some(mut room) = self.state.get_room_stats(&room_id).await.expect("Failed to get room") {
             // SECURITY FIX: Only room host can update settings
             if room.host != user_id {
                 return PaymentResult::PaymentError { error: PaymentError::Unauthorized };
             }
 
+            if !room.is_open {
+                return PaymentResult::PaymentError { error: PaymentError::RoomClosed };
+            }
+
             // Validate settings
-            if settings.min_tip == 0 {
+            if settings.min_tip == Amount::ZERO {
                 return PaymentResult::PaymentError { error: PaymentError::InvalidRoomSettings };
             }
 
             room.settings = settings;
-            self.state.insert_room(room_id.clone(), room);
+            self.state.insert_room(room_id.clone(), room).await.expect("Failed to update room");
             PaymentResult::RoomSettingsUpdated { room_id }
         } else {
             PaymentResult::PaymentError { error: PaymentError::RoomNotFound }
         }
     }
 
-    /// Withdraw funds for room host
-    fn withdraw_funds(&mut self, room_id: RoomId, amount: u128) -> PaymentResult {
+    /// Pay settled revenue out to the room host's account on this chain, or to `destination`
+    async fn withdraw_funds(&mut self, room_id: RoomId, amount: Amount, destination: Option<Account>) -> PaymentResult {
         // SECURITY FIX: Check authentication
-        let user_id = match self.runtime.authenticated_signer() {
-            Some(signer) => signer.to_string(),
+        let owner = match self.runtime.authenticated_signer() {
+            Some(signer) => signer,
             None => return PaymentResult::PaymentError { error: PaymentError::Unauthorized },
         };
+        let user_id = owner.to_string();
 
-        if let Some(room) = self.state.get_room_stats(&room_id) {
-            // SECURITY FIX: Only room host can withdraw funds
-            if room.host != user_id {
-                return PaymentResult::PaymentError { error: PaymentError::Unauthorized };
-            }
+        let Some(mut room) = self.state.get_room_stats(&room_id).await.expect("Failed to get room") else {
+            return PaymentResult::PaymentError { error: PaymentError::RoomNotFound };
+        };
 
-            // Validate amount
-            if amount == 0 {
-                return PaymentResult::PaymentError { error: PaymentError::InvalidAmount };
+        // SECURITY FIX: Only room host can withdraw funds
+        if room.host != user_id {
+            return PaymentResult::PaymentError { error: PaymentError::Unauthorized };
+        }
+
+        // Validate amount
+        if amount == Amount::ZERO {
+            return PaymentResult::PaymentError { error: PaymentError::InvalidAmount };
+        }
+
+        // Pending payments become withdrawable once they are settled
+        room.available = match room.available.try_sub(amount) {
+            Ok(available) => available,
+            Err(_) => return PaymentResult::PaymentError { error: PaymentError::InsufficientBalance },
+        };
+        room.withdrawn = room.withdrawn.saturating_add(amount);
+        let available = room.available;
+        self.state.insert_room(room_id.clone(), room).await.expect("Failed to update room");
+
+        let destination = destination.unwrap_or(Account {
+            chain_id: self.runtime.chain_id(),
+            owner,
+        });
+        self.send_tokens(destination, amount);
+
+        let withdrawal = Withdrawal {
+            room_id: room_id.clone(),
+            amount,
+            destination,
+            timestamp: self.runtime.system_time().micros(),
+        };
+        let withdrawal_hash: Hash = CryptoHash::new(&withdrawal).into();
+
+        PaymentResult::FundsWithdrawn { room_id, amount, available, withdrawal_hash }
+    }
+
+    /// Accept a room lifecycle change from the room manager and apply it on the pool chain
+    async fn receive_room_lifecycle(&mut self, event: RoomLifecycle) -> PaymentResult {
+        // SECURITY: Only the configured room manager may drive room lifecycles
+        let parameters = self.runtime.application_parameters();
+        let caller = self.runtime.authenticated_caller_id();
+        if parameters.room_manager.is_none() || caller != parameters.room_manager {
+            return PaymentResult::PaymentError { error: PaymentError::Unauthorized };
+        }
+
+        let room_id = event.room_id().clone();
+        let forwarded = match parameters.pool_chain {
+            Some(pool_chain) if pool_chain != self.runtime.chain_id() => {
+                self.runtime
+                    .prepare_message(PaymentMessage::RoomLifecycle(event))
+                    .with_tracking()
+                    .send_to(pool_chain);
+                true
+            }
+            _ => {
+                let origin = self.runtime.chain_id();
+                if !self.apply_room_lifecycle(event, origin).await {
+                    return PaymentResult::PaymentError { error: PaymentError::Unauthorized };
+                }
+                false
             }
+        };
+
+        PaymentResult::RoomLifecycleApplied { room_id, forwarded }
+    }
 
-            let available_funds = room.total_tips + room.total_access_fees;
-            if amount <= available_funds {
-                // SECURITY FIX: Use proper hash for withdrawal
-                let timestamp = self.runtime.system_time().micros_since_epoch();
-                let withdrawal_data = format!("{}:{}:{}", room_id, amount, timestamp);
-                let withdrawal_hash = linera_sdk::base::crypto::Hash::new(withdrawal_data.as_bytes()).0;
+    /// Mirror a room lifecycle change reported from the `origin` chain into the room's payment pool
+    ///
+    /// A pool follows the chain that opened it, so changes reported from any other chain are
+    /// dropped; returns whether the change was accepted.
+    async fn apply_room_lifecycle(&mut self, event: RoomLifecycle, origin: ChainId) -> bool {
+        let existing = self.state.get_room_stats(event.room_id()).await.expect("Failed to get room");
+        // SECURITY: Another chain's room manager must not take over or close this room's pool
+        if existing.as_ref().is_some_and(|pool| pool.origin_chain != Some(origin)) {
+            return false;
+        }
 
-                PaymentResult::FundsWithdrawn { room_id, amount, withdrawal_hash }
-            } else {
-                PaymentResult::PaymentError { error: PaymentError::InsufficientBalance }
+        match event {
+            RoomLifecycle::Opened { room_id, host, tier, relays } => {
+                let (pool, reopened) = match existing {
+                    // A reopened room keeps its revenue history
+                    Some(mut pool) => {
+                        let reopened = !pool.is_open;
+                        pool.host = host;
+                        pool.tier = tier;
+                        pool.relays = relays;
+                        pool.settings.payments_enabled = true;
+                        pool.is_open = true;
+                        (pool, reopened)
+                    }
+                    None => {
+                        let pool = RoomPaymentPool::new(room_id.clone(), host, RoomPaymentSettings::default());
+                        (RoomPaymentPool { tier, relays, origin_chain: Some(origin), ..pool }, true)
+                    }
+                };
+                self.state.insert_room(room_id, pool).await.expect("Failed to update room");
+                if reopened {
+                    self.count_active_room(true);
+                }
             }
+            RoomLifecycle::HostChanged { room_id, host, tier } => {
+                if let Some(mut pool) = existing {
+                    pool.host = host;
+                    pool.tier = tier;
+                    self.state.insert_room(room_id, pool).await.expect("Failed to update room");
+                }
+            }
+            RoomLifecycle::RelaysChanged { room_id, relays } => {
+                if let Some(mut pool) = existing {
+                    pool.relays = relays;
+                    self.state.insert_room(room_id, pool).await.expect("Failed to update room");
+                }
+            }
+            RoomLifecycle::RecordingAttached { room_id, blob_id, replay_price } => {
+                if let Some(mut pool) = existing {
+                    pool.replay_prices.insert(blob_id, replay_price);
+                    self.state.insert_room(room_id, pool).await.expect("Failed to update room");
+                }
+            }
+            RoomLifecycle::Closed { .. } => {
+                if let Some(pool) = existing.filter(|pool| pool.is_open) {
+                    self.close_pool(pool).await;
+                }
+            }
+        }
+        true
+    }
+
+    /// Stop a pool from accepting payments and settle what is still pending
+    ///
+    /// Returns the amount that became available to the host.
+    async fn close_pool(&mut self, mut pool: RoomPaymentPool) -> Amount {
+        let room_id = pool.room_id.clone();
+        pool.is_open = false;
+        pool.settings.payments_enabled = false;
+        self.state.insert_room(room_id.clone(), pool).await.expect("Failed to update room");
+        self.count_active_room(false);
+        self.settle_room(&room_id).await
+    }
+
+    /// Keep `PaymentStats::active_rooms` in step with pools opening and closing
+    fn count_active_room(&mut self, opened: bool) {
+        let mut stats = self.state.get_global_stats().clone();
+        stats.active_rooms = if opened {
+            stats.active_rooms.saturating_add(1)
         } else {
-            PaymentResult::PaymentError { error: PaymentError::RoomNotFound }
+            stats.active_rooms.saturating_sub(1)
+        };
+        self.state.update_stats(stats);
+    }
+
+    /// Settle every pending payment of a room, on both the room and user side
+    ///
+    /// The host's share becomes available to withdraw; the settled total is returned.
+    async fn settle_room(&mut self, room_id: &RoomId) -> Amount {
+        let Some(mut pool) = self.state.get_room_stats(room_id).await.expect("Failed to get room") else {
+            return Amount::ZERO;
+        };
+
+        let mut payers: Vec<UserId> = pool.pending_tips.iter().map(|tip| tip.from.clone())
+            .chain(pool.pending_access_fees.iter().map(|fee| fee.user_id.clone()))
+            .chain(pool.pending_replay_fees.iter().map(|fee| fee.user_id.clone()))
+            .collect();
+        payers.sort();
+        payers.dedup();
+
+        for payer in payers {
+            if let Some(mut user_state) = self.state.get_user_state(&payer).await.expect("Failed to get user") {
+                let (settled, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut user_state.pending_transactions)
+                    .into_iter()
+                    .partition(|tx| tx.room_id() == room_id);
+                user_state.pending_transactions = kept;
+                self.state.insert_user(payer.clone(), user_state).await.expect("Failed to update user");
+                self.record_settlement(&payer, settled).await;
+            }
+        }
+
+        let settled = pool.take_all_pending();
+        self.credit_settled(&mut pool, settled).await;
+        self.state.insert_room(room_id.clone(), pool).await.expect("Failed to update room");
+        settled
+    }
+
+    /// Hash transactions a user settles together and log one record per room they paid into
+    ///
+    /// Returns the settlement hash.
+    async fn record_settlement(&mut self, user_id: &UserId, transactions: Vec<PendingTransaction>) -> Hash {
+        let batch = SettlementBatch {
+            user_id: user_id.clone(),
+            transactions,
+            timestamp: self.runtime.system_time().micros(),
+        };
+        let settlement_hash: Hash = CryptoHash::new(&batch).into();
+
+        let mut per_room: BTreeMap<RoomId, (usize, Amount)> = BTreeMap::new();
+        for tx in &batch.transactions {
+            let (count, amount) = per_room.entry(tx.room_id().clone()).or_default();
+            *count += 1;
+            *amount = amount.saturating_add(tx.amount());
+        }
+
+        for (room_id, (transaction_count, amount)) in per_room {
+            let record = SettlementRecord {
+                user_id: user_id.clone(),
+                room_id,
+                transaction_count,
+                amount,
+                settlement_hash,
+                timestamp: batch.timestamp,
+            };
+            self.state.record_settlement(record).await.expect("Failed to record settlement");
+        }
+
+        settlement_hash
+    }
+
+    /// Split settled revenue of a room between its host, the platform treasury and its relay nodes
+    ///
+    /// The caller stores the updated pool.
+    async fn credit_settled(&mut self, pool: &mut RoomPaymentPool, gross: Amount) {
+        if gross == Amount::ZERO {
+            return;
+        }
+
+        let parameters = self.runtime.application_parameters();
+        let mut split = parameters.fee_schedule.split(pool.tier, gross, pool.relays.len());
+        match parameters.treasury {
+            Some(treasury) if split.platform > Amount::ZERO => {
+                self.send_tokens(treasury, split.platform);
+                let mut stats = self.state.get_global_stats().clone();
+                stats.platform_fees = stats.platform_fees.saturating_add(split.platform);
+                self.state.update_stats(stats);
+            }
+            Some(_) => {}
+            None => {
+                split.host = split.host.saturating_add(split.platform);
+                split.platform = Amount::ZERO;
+            }
+        }
+
+        if split.per_relay > Amount::ZERO {
+            for relay in pool.relays.clone() {
+                self.credit_balance(relay, split.per_relay).await;
+            }
+        }
+
+        pool.gross_settled = pool.gross_settled.saturating_add(gross);
+        pool.platform_fees = pool.platform_fees.saturating_add(split.platform);
+        pool.relay_fees = pool.relay_fees.saturating_add(split.per_relay.saturating_mul(pool.relays.len() as u128));
+        pool.available = pool.available.saturating_add(split.host);
+    }
+
+    /// Add earnings to a user's payment balance, opening their payment state if needed
+    async fn credit_balance(&mut self, user_id: UserId, amount: Amount) {
+        let existing = self.state.get_user_state(&user_id).await.expect("Failed to get user");
+        let is_new_user = existing.is_none();
+        let mut user_state = existing.unwrap_or_else(|| UserPaymentState::new(user_id.clone()));
+        user_state.balance = user_state.balance.saturating_add(amount);
+        self.state.insert_user(user_id, user_state).await.expect("Failed to update user");
+
+        if is_new_user {
+            let mut stats = self.state.get_global_stats().clone();
+            stats.active_users += 1;
+            self.state.update_stats(stats);
         }
     }
 
     /// Check if user should auto-settle transactions
-    fn should_auto_settle(&self, user_id: &UserId) -> bool {
-        if let Some(user_state) = self.state.get_user_state(user_id) {
-            let pending_count = user_state.pending_transactions.len();
-            let threshold_reached = user_state.balance >= user_state.preferences.auto_settle_threshold;
-            let max_pending_reached = pending_count >= user_state.preferences.max_pending;
-            threshold_reached || max_pending_reached
-        } else {
-            false
+    async fn should_auto_settle(&self, user_id: &UserId) -> bool {
+        self.state.get_user_state(user_id).await
+            .expect("Failed to get user")
+            .is_some_and(|user_state| user_state.should_auto_settle())
+    }
+
+    /// Returns the account that holds deposited tokens on this chain
+    fn escrow_account(&mut self) -> Account {
+        Account {
+            chain_id: self.runtime.chain_id(),
+            owner: AccountOwner::from(self.runtime.application_id().forget_abi()),
         }
     }
-}
\ No newline at end of file
+
+    /// Pull tokens from the signer's account into the escrow account
+    fn receive_tokens(&mut self, owner: AccountOwner, amount: Amount) {
+        let escrow = self.escrow_account();
+        match self.runtime.application_parameters().token {
+            None => {
+                self.runtime.transfer(owner, escrow, amount);
+            }
+            Some(token_id) => {
+                let transfer = FungibleOperation::Transfer {
+                    owner,
+                    amount,
+                    target_account: escrow,
+                };
+                self.runtime
+                    .call_application(true, token_id.with_abi::<FungibleTokenAbi>(), &transfer);
+            }
+        }
+    }
+
+    /// Pay tokens out of the escrow account
+    fn send_tokens(&mut self, destination: Account, amount: Amount) {
+        let escrow = self.escrow_account();
+        match self.runtime.application_parameters().token {
+            None => {
+                self.runtime.transfer(escrow.owner, destination, amount);
+            }
+            Some(token_id) => {
+                let transfer = FungibleOperation::Transfer {
+                    owner: escrow.owner,
+                    amount,
+                    target_account: destination,
+                };
+                self.runtime
+                    .call_application(true, token_id.with_abi::<FungibleTokenAbi>(), &transfer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chillie_payment_processor::{FeeSchedule, StakingTier};
+    use linera_sdk::{
+        linera_base_types::{self, ApplicationId},
+        util::BlockingWait,
+    };
+
+    fn chain(name: &str) -> ChainId {
+        ChainId(CryptoHash::test_hash(name))
+    }
+
+    fn owner(name: &str) -> AccountOwner {
+        AccountOwner::from(CryptoHash::test_hash(name))
+    }
+
+    fn create_and_instantiate(parameters: PaymentParameters) -> PaymentProcessorContract {
+        let application_id = ApplicationId::new(CryptoHash::test_hash("payment processor"))
+            .with_abi::<PaymentProcessorAbi>();
+        let runtime = ContractRuntime::new()
+            .with_application_parameters(parameters)
+            .with_application_id(application_id)
+            .with_chain_id(chain("pool"))
+            .with_message_is_bouncing(Some(false))
+            .with_system_time(linera_base_types::Timestamp::from(0))
+            .with_owner_balances(["alice", "bob"].map(|name| (owner(name), Amount::from_tokens(1_000))));
+        let mut contract = PaymentProcessorContract {
+            state: PaymentProcessorState::load(runtime.root_view_storage_context())
+                .blocking_wait()
+                .expect("Failed to read from mock key value store"),
+            runtime,
+        };
+        contract.instantiate(()).blocking_wait();
+        contract
+    }
+
+    fn managed_parameters() -> PaymentParameters {
+        PaymentParameters {
+            room_manager: Some(ApplicationId::new(CryptoHash::test_hash("room manager"))),
+            pool_chain: Some(chain("pool")),
+            ..PaymentParameters::default()
+        }
+    }
+
+    fn execute(contract: &mut PaymentProcessorContract, signer: &str, operation: PaymentOperation) -> PaymentResult {
+        contract.runtime.set_authenticated_signer(owner(signer));
+        contract.execute_operation(operation).blocking_wait()
+    }
+
+    fn assert_rejected(result: PaymentResult, expected: PaymentError) {
+        match result {
+            PaymentResult::PaymentError { error } => assert_eq!(error, expected),
+            result => panic!("Expected {:?}, got {:?}", expected, result),
+        }
+    }
+
+    fn receive_lifecycle(contract: &mut PaymentProcessorContract, origin: &str, event: RoomLifecycle) {
+        contract.runtime.set_message_origin_chain_id(chain(origin));
+        contract.execute_message(PaymentMessage::RoomLifecycle(event)).blocking_wait();
+    }
+
+    fn pool(contract: &PaymentProcessorContract, room_id: &str) -> RoomPaymentPool {
+        contract.state.get_room_stats(&room_id.to_string())
+            .blocking_wait()
+            .expect("Failed to get room")
+            .expect("Room should have a payment pool")
+    }
+
+    fn tip(contract: &mut PaymentProcessorContract, signer: &str, room_id: &str, amount: Amount) {
+        let result = execute(contract, signer, PaymentOperation::SendTip {
+            room_id: room_id.to_string(),
+            amount,
+            message: None,
+            super_chat: false,
+        });
+        assert!(matches!(result, PaymentResult::TipSent { .. }), "Tip failed: {:?}", result);
+    }
+
+    fn opened(room_id: &str, host: &str) -> RoomLifecycle {
+        RoomLifecycle::Opened {
+            room_id: room_id.to_string(),
+            host: owner(host).to_string(),
+            tier: StakingTier::Basic,
+            relays: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_from_another_chain_is_dropped() {
+        let mut contract = create_and_instantiate(managed_parameters());
+        receive_lifecycle(&mut contract, "room chain", opened("room", "host"));
+        assert_eq!(pool(&contract, "room").origin_chain, Some(chain("room chain")));
+
+        receive_lifecycle(&mut contract, "other chain", opened("room", "mallory"));
+        receive_lifecycle(&mut contract, "other chain", RoomLifecycle::HostChanged {
+            room_id: "room".to_string(),
+            host: owner("mallory").to_string(),
+            tier: StakingTier::RelayNode,
+        });
+        receive_lifecycle(&mut contract, "other chain", RoomLifecycle::Closed { room_id: "room".to_string() });
+
+        let room = pool(&contract, "room");
+        assert_eq!(room.host, owner("host").to_string());
+        assert_eq!(room.tier, StakingTier::Basic);
+        assert!(room.is_open);
+
+        receive_lifecycle(&mut contract, "room chain", RoomLifecycle::Closed { room_id: "room".to_string() });
+        assert!(!pool(&contract, "room").is_open);
+        assert_eq!(contract.state.get_global_stats().active_rooms, 0);
+    }
+
+    #[test]
+    fn test_deposits_can_be_withdrawn_back_to_the_signer() {
+        let mut contract = create_and_instantiate(PaymentParameters::default());
+
+        let result = execute(&mut contract, "alice", PaymentOperation::Deposit { amount: Amount::ZERO });
+        assert_rejected(result, PaymentError::InvalidAmount);
+        let result = execute(&mut contract, "alice", PaymentOperation::WithdrawBalance { amount: Amount::ONE });
+        assert_rejected(result, PaymentError::UserNotFound);
+
+        let result = execute(&mut contract, "alice", PaymentOperation::Deposit { amount: Amount::from_tokens(10) });
+        assert!(
+            matches!(result, PaymentResult::Deposited { balance, .. } if balance == Amount::from_tokens(10)),
+            "Unexpected result: {:?}", result,
+        );
+        assert_eq!(contract.runtime.owner_balance(owner("alice")), Amount::from_tokens(990));
+        assert_eq!(contract.state.get_global_stats().active_users, 1);
+
+        let result = execute(&mut contract, "alice", PaymentOperation::WithdrawBalance { amount: Amount::from_tokens(11) });
+        assert_rejected(result, PaymentError::InsufficientBalance);
+
+        let result = execute(&mut contract, "alice", PaymentOperation::WithdrawBalance { amount: Amount::from_tokens(4) });
+        assert!(
+            matches!(result, PaymentResult::BalanceWithdrawn { balance, .. } if balance == Amount::from_tokens(6)),
+            "Unexpected result: {:?}", result,
+        );
+        assert_eq!(contract.runtime.owner_balance(owner("alice")), Amount::from_tokens(994));
+    }
+
+    #[test]
+    fn test_settlement_splits_revenue_between_host_treasury_and_relays() {
+        let treasury = Account { chain_id: chain("pool"), owner: owner("treasury") };
+        let mut contract = create_and_instantiate(PaymentParameters {
+            treasury: Some(treasury),
+            fee_schedule: FeeSchedule { relay_share: 10, ..FeeSchedule::default() },
+            ..managed_parameters()
+        });
+        receive_lifecycle(&mut contract, "room chain", RoomLifecycle::Opened {
+            room_id: "room".to_string(),
+            host: owner("host").to_string(),
+            tier: StakingTier::Basic,
+            relays: vec![owner("relay").to_string()],
+        });
+        execute(&mut contract, "alice", PaymentOperation::Deposit { amount: Amount::from_tokens(10) });
+        tip(&mut contract, "alice", "room", Amount::from_tokens(5));
+
+        // Tips stay pending, and out of the host's reach, until the payer settles them
+        let room = pool(&contract, "room");
+        assert_eq!(room.pending_tips.len(), 1);
+        assert_eq!(room.available, Amount::ZERO);
+        let result = execute(&mut contract, "bob", PaymentOperation::SettlePendingTransactions {
+            user_id: owner("alice").to_string(),
+        });
+        assert_rejected(result, PaymentError::Unauthorized);
+
+        let result = execute(&mut contract, "alice", PaymentOperation::SettlePendingTransactions {
+            user_id: owner("alice").to_string(),
+        });
+        assert!(
+            matches!(
+                result,
+                PaymentResult::TransactionsSettled { transaction_count: 1, total_amount, .. }
+                    if total_amount == Amount::from_tokens(5)
+            ),
+            "Unexpected result: {:?}", result,
+        );
+
+        // Basic hosts pay a 30% platform fee and the relay takes 10%
+        let room = pool(&contract, "room");
+        assert!(room.pending_tips.is_empty());
+        assert_eq!(room.gross_settled, Amount::from_tokens(5));
+        assert_eq!(room.platform_fees, Amount::from_millis(1500));
+        assert_eq!(room.relay_fees, Amount::from_millis(500));
+        assert_eq!(room.available, Amount::from_tokens(3));
+        assert_eq!(contract.runtime.owner_balance(owner("treasury")), Amount::from_millis(1500));
+        let relay = contract.state.get_user_state(&owner("relay").to_string())
+            .blocking_wait()
+            .expect("Failed to get user")
+            .expect("Relay should have been credited");
+        assert_eq!(relay.balance, Amount::from_millis(500));
+
+        let result = execute(&mut contract, "host", PaymentOperation::WithdrawFunds {
+            room_id: "room".to_string(),
+            amount: Amount::from_tokens(4),
+            destination: None,
+        });
+        assert_rejected(result, PaymentError::InsufficientBalance);
+        let result = execute(&mut contract, "host", PaymentOperation::WithdrawFunds {
+            room_id: "room".to_string(),
+            amount: Amount::from_tokens(3),
+            destination: None,
+        });
+        assert!(
+            matches!(result, PaymentResult::FundsWithdrawn { available, .. } if available == Amount::ZERO),
+            "Unexpected result: {:?}", result,
+        );
+        assert_eq!(contract.runtime.owner_balance(owner("host")), Amount::from_tokens(3));
+    }
+
+    #[test]
+    fn test_settlements_are_logged_per_room_and_paged_per_user() {
+        let mut contract = create_and_instantiate(managed_parameters());
+        receive_lifecycle(&mut contract, "room chain", opened("talk", "host"));
+        receive_lifecycle(&mut contract, "room chain", opened("show", "host"));
+        execute(&mut contract, "alice", PaymentOperation::Deposit { amount: Amount::from_tokens(5) });
+        tip(&mut contract, "alice", "talk", Amount::from_tokens(1));
+        tip(&mut contract, "alice", "talk", Amount::from_tokens(1));
+        tip(&mut contract, "alice", "show", Amount::from_tokens(2));
+
+        let result = execute(&mut contract, "alice", PaymentOperation::SettlePendingTransactions {
+            user_id: owner("alice").to_string(),
+        });
+        let PaymentResult::TransactionsSettled { settlement_hash, .. } = result else {
+            panic!("Unexpected result: {:?}", result);
+        };
+
+        let alice = owner("alice").to_string();
+        let first = contract.state.user_settlements_page(&alice, None, 1)
+            .blocking_wait()
+            .expect("Failed to read settlements");
+        assert_eq!(first.settlements.len(), 1);
+        assert_eq!(first.next_cursor, Some(0));
+        let second = contract.state.user_settlements_page(&alice, first.next_cursor, 1)
+            .blocking_wait()
+            .expect("Failed to read settlements");
+        assert_eq!(second.settlements.len(), 1);
+        assert_eq!(second.next_cursor, None);
+
+        let records: Vec<_> = first.settlements.into_iter().chain(second.settlements).collect();
+        assert!(records.iter().all(|record| record.settlement_hash == settlement_hash));
+        let talk = records.iter().find(|record| record.room_id == "talk").expect("Missing talk settlement");
+        assert_eq!(talk.transaction_count, 2);
+        assert_eq!(talk.amount, Amount::from_tokens(2));
+
+        let show = contract.state.room_settlements_page(&"show".to_string(), None, 10)
+            .blocking_wait()
+            .expect("Failed to read settlements");
+        assert_eq!(show.settlements.len(), 1);
+        assert_eq!(show.settlements[0].user_id, alice);
+        assert_eq!(show.settlements[0].amount, Amount::from_tokens(2));
+    }
+}
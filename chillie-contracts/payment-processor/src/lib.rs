@@ -1,14 +1,11 @@
 // Chillie Payment Processor - Micropayment Framework
 // Handles tipping, access fees, and batched settlements for video streaming
 
-use linera_sdk::linera_base_types::{ContractAbi, ServiceAbi};
+use async_graphql::{ComplexObject, Enum, InputObject, Request, Response, SimpleObject, Union};
+use linera_sdk::linera_base_types::{Account, Amount, ApplicationId, ChainId, ContractAbi, ServiceAbi};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-pub mod contract;
-pub mod service;
-pub mod state;
-
 pub struct PaymentProcessorAbi;
 
 impl ContractAbi for PaymentProcessorAbi {
@@ -17,78 +14,222 @@ impl ContractAbi for PaymentProcessorAbi {
 }
 
 impl ServiceAbi for PaymentProcessorAbi {
-    type Query = PaymentQuery;
-    type QueryResponse = PaymentQueryResponse;
+    type Query = Request;
+    type QueryResponse = Response;
+}
+
+/// Application parameters of the payment processor
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PaymentParameters {
+    /// Room-manager application allowed to report room lifecycle changes
+    pub room_manager: Option<ApplicationId>,
+    /// Chain holding the payment pools; lifecycle changes reported elsewhere are forwarded there
+    pub pool_chain: Option<ChainId>,
+    /// Fungible token application that user balances are held in; native tokens when unset
+    ///
+    /// Balances, tips and prices are counted in attos of this token.
+    pub token: Option<ApplicationId>,
+    /// Account receiving platform fees; hosts keep the platform's share while unset
+    #[serde(default)]
+    pub treasury: Option<Account>,
+    /// Platform fee per host tier and the relay nodes' share of settled revenue
+    #[serde(default)]
+    pub fee_schedule: FeeSchedule,
+}
+
+/// Staking tier of a user; the room manager reports the tier of each room's host
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default, Enum)]
+pub enum StakingTier {
+    #[default]
+    Basic,      // 0 stake
+    Podcast,    // 100 stake
+    SoloStream, // 500 stake
+    MultiStream,// 2000 stake
+    RelayNode,  // 5000 stake
 }
 
-// Re-export commonly used types
-pub use contract::PaymentProcessorContract;
-pub use service::PaymentProcessorService;
-pub use state::PaymentProcessorState;
+impl StakingTier {
+    /// Default stake threshold of the tier; deployments override it through the room manager's `TierConfig`
+    pub fn required_stake(&self) -> Amount {
+        match self {
+            StakingTier::Basic => Amount::from_tokens(0),
+            StakingTier::Podcast => Amount::from_tokens(100),
+            StakingTier::SoloStream => Amount::from_tokens(500),
+            StakingTier::MultiStream => Amount::from_tokens(2000),
+            StakingTier::RelayNode => Amount::from_tokens(5000),
+        }
+    }
+}
 
-/// Query types for payment processor
+/// Fees taken from a room's revenue when it is settled, in percent of the settled amount
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum PaymentQuery {
-    GetRoomStats { room_id: RoomId },
-    GetUserState { user_id: UserId },
-    GetGlobalStats,
-    GetPendingTips { room_id: RoomId },
-    GetPendingAccessFees { room_id: RoomId },
-    GetUserPendingTransactions { user_id: UserId },
-    GetQualityTierPricing { room_id: RoomId },
-    GetUserPaymentSummary { user_id: UserId, room_id: RoomId },
-    GetRoomRevenueBreakdown { room_id: RoomId },
-}
-
-/// Query response types
+pub struct FeeSchedule {
+    pub basic: u8,
+    pub podcast: u8,
+    pub solo_stream: u8,
+    pub multi_stream: u8,
+    pub relay_node: u8,
+    /// Share split evenly between the relay nodes serving the room
+    pub relay_share: u8,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self {
+            basic: 30,
+            podcast: 15,
+            solo_stream: 15,
+            multi_stream: 3,
+            relay_node: 3,
+            relay_share: 0,
+        }
+    }
+}
+
+impl FeeSchedule {
+    pub fn platform_fee(&self, tier: StakingTier) -> u8 {
+        match tier {
+            StakingTier::Basic => self.basic,
+            StakingTier::Podcast => self.podcast,
+            StakingTier::SoloStream => self.solo_stream,
+            StakingTier::MultiStream => self.multi_stream,
+            StakingTier::RelayNode => self.relay_node,
+        }
+    }
+
+    /// Checks that no tier gives away more than the settled amount
+    pub fn is_valid(&self) -> bool {
+        [self.basic, self.podcast, self.solo_stream, self.multi_stream, self.relay_node]
+            .into_iter()
+            .all(|fee| fee as u16 + self.relay_share as u16 <= 100)
+    }
+
+    /// Split settled revenue of a room hosted at `tier` and served by `relay_count` relay nodes
+    ///
+    /// Rounding dust goes to the host.
+    pub fn split(&self, tier: StakingTier, gross: Amount, relay_count: usize) -> RevenueSplit {
+        let gross = u128::from(gross);
+        let platform = percent_of(gross, self.platform_fee(tier));
+        let per_relay = match relay_count {
+            0 => 0,
+            count => percent_of(gross, self.relay_share) / count as u128,
+        };
+        let host = gross - platform - per_relay * relay_count as u128;
+        RevenueSplit {
+            host: Amount::from_attos(host),
+            platform: Amount::from_attos(platform),
+            per_relay: Amount::from_attos(per_relay),
+        }
+    }
+}
+
+/// Shares of a settled amount
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RevenueSplit {
+    pub host: Amount,
+    pub platform: Amount,
+    /// Paid to each relay node of the room
+    pub per_relay: Amount,
+}
+
+fn percent_of(attos: u128, percent: u8) -> u128 {
+    attos / 100 * percent as u128 + attos % 100 * percent as u128 / 100
+}
+
+/// Room lifecycle changes reported by the room-manager application
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum PaymentQueryResponse {
-    RoomStats(Option<RoomPaymentPool>),
-    UserState(Option<UserPaymentState>),
-    GlobalStats(PaymentStats),
-    PendingTips(Vec<PendingTip>),
-    PendingAccessFees(Vec<PendingAccessFee>),
-    UserPendingTransactions(Vec<PendingTransaction>),
-    QualityTierPricing(HashMap<StreamQuality, u64>),
-    UserPaymentSummary(UserPaymentSummary),
-    RoomRevenueBreakdown(RoomRevenueBreakdown),
-    Error(PaymentError),
+pub enum RoomLifecycle {
+    /// A room was created; its payment pool is opened with default settings
+    Opened { room_id: RoomId, host: UserId, tier: StakingTier, relays: Vec<UserId> },
+    /// The room was handed over to a new host
+    HostChanged { room_id: RoomId, host: UserId, tier: StakingTier },
+    /// The relay nodes serving the room changed
+    RelaysChanged { room_id: RoomId, relays: Vec<UserId> },
+    /// The room was closed; pending payments are settled
+    Closed { room_id: RoomId },
+    /// A recording of the room was published with a replay access price
+    RecordingAttached { room_id: RoomId, blob_id: BlobId, replay_price: Amount },
 }
 
-/// Payment pool for a specific room
+impl RoomLifecycle {
+    pub fn room_id(&self) -> &RoomId {
+        match self {
+            RoomLifecycle::Opened { room_id, .. }
+            | RoomLifecycle::HostChanged { room_id, .. }
+            | RoomLifecycle::RelaysChanged { room_id, .. }
+            | RoomLifecycle::Closed { room_id }
+            | RoomLifecycle::RecordingAttached { room_id, .. } => room_id,
+        }
+    }
+}
+
+/// Cross-chain messages between instances of the payment processor
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PaymentMessage {
+    /// Applies a room lifecycle change on the pool chain
+    RoomLifecycle(RoomLifecycle),
+}
+
+/// Payment pool for a specific room
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct RoomPaymentPool {
     /// Room identifier
     pub room_id: RoomId,
     /// Room host (streamer) who receives payments
     pub host: UserId,
+    /// Staking tier of the host, which sets the platform fee
+    pub tier: StakingTier,
+    /// Relay nodes sharing in the room's revenue
+    pub relays: Vec<UserId>,
     /// Total tips received
-    pub total_tips: u128,
+    pub total_tips: Amount,
     /// Total access fees collected
-    pub total_access_fees: u128,
+    pub total_access_fees: Amount,
+    /// Settled revenue the host can withdraw
+    pub available: Amount,
+    /// Revenue already paid out to the host
+    pub withdrawn: Amount,
+    /// Settled payments before fees
+    pub gross_settled: Amount,
+    /// Fees paid to the platform treasury
+    pub platform_fees: Amount,
+    /// Revenue paid to the room's relay nodes
+    pub relay_fees: Amount,
     /// Active tips from users (pending batch settlement)
     pub pending_tips: Vec<PendingTip>,
     /// Active access fees (pending batch settlement)
     pub pending_access_fees: Vec<PendingAccessFee>,
+    /// Replay purchases (pending batch settlement)
+    pub pending_replay_fees: Vec<PendingReplayFee>,
+    /// Replay access price per recording
+    #[graphql(skip)]
+    pub replay_prices: HashMap<BlobId, Amount>,
     /// Payment settings for the room
     pub settings: RoomPaymentSettings,
+    /// Whether the room is live; closed pools only sell replays
+    pub is_open: bool,
+    /// Chain whose room manager reports the room's lifecycle; unset for pools opened directly
+    pub origin_chain: Option<ChainId>,
 }
 
 /// Payment settings for a room
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct RoomPaymentSettings {
     /// Minimum tip amount
-    pub min_tip: u64,
+    pub min_tip: Amount,
     /// Access fee for private rooms (0 for public)
-    pub access_fee: u64,
+    pub access_fee: Amount,
     /// Quality tier pricing
-    pub quality_tiers: HashMap<StreamQuality, u64>,
+    #[graphql(skip)]
+    pub quality_tiers: HashMap<StreamQuality, Amount>,
     /// Whether payments are enabled
     pub payments_enabled: bool,
 }
 
 /// Stream quality levels
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Enum)]
 pub enum StreamQuality {
     Standard,
     High,
@@ -96,6 +237,28 @@ pub enum StreamQuality {
     Ultra,
 }
 
+/// Price of one quality tier
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "QualityPriceInput")]
+pub struct QualityPrice {
+    pub quality: StreamQuality,
+    pub price: Amount,
+}
+
+/// Replay access price of one recording
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ReplayPrice {
+    pub blob_id: BlobId,
+    pub price: Amount,
+}
+
+/// Revenue collected for one quality tier
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct QualityRevenue {
+    pub quality: StreamQuality,
+    pub amount: Amount,
+}
+
 impl std::fmt::Display for StreamQuality {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -108,12 +271,12 @@ impl std::fmt::Display for StreamQuality {
 }
 
 /// User payment state
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct UserPaymentState {
     /// User identifier
     pub user_id: UserId,
     /// User's current balance
-    pub balance: u128,
+    pub balance: Amount,
     /// User's pending batch transactions
     pub pending_transactions: Vec<PendingTransaction>,
     /// User's payment preferences
@@ -121,23 +284,25 @@ pub struct UserPaymentState {
 }
 
 /// User payment preferences
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct UserPaymentPreferences {
     /// Default tip amount
-    pub default_tip: u64,
-    /// Auto-settle threshold
-    pub auto_settle_threshold: u128,
+    pub default_tip: Amount,
+    /// Pending total at which transactions are settled automatically
+    pub auto_settle_threshold: Amount,
     /// Maximum pending transactions before auto-settlement
     pub max_pending: usize,
 }
 
 /// Pending tip transaction
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct PendingTip {
+    /// Room receiving the tip
+    pub room_id: RoomId,
     /// Sender user ID
     pub from: UserId,
     /// Tip amount
-    pub amount: u64,
+    pub amount: Amount,
     /// Optional message
     pub message: Option<String>,
     /// Timestamp
@@ -147,47 +312,207 @@ pub struct PendingTip {
 }
 
 /// Pending access fee transaction
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct PendingAccessFee {
+    /// Room the fee was paid for
+    pub room_id: RoomId,
     /// User paying the fee
     pub user_id: UserId,
     /// Amount paid
-    pub amount: u64,
+    pub amount: Amount,
     /// Quality tier purchased
     pub quality_tier: StreamQuality,
     /// Timestamp
     pub timestamp: Timestamp,
 }
 
+/// Pending replay access purchase
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct PendingReplayFee {
+    /// Room the recording belongs to
+    pub room_id: RoomId,
+    /// User buying replay access
+    pub user_id: UserId,
+    /// Walrus blob id of the recording
+    pub blob_id: BlobId,
+    /// Amount paid
+    pub amount: Amount,
+    /// Timestamp
+    pub timestamp: Timestamp,
+}
+
 /// Pending transaction (batchable)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Union)]
 pub enum PendingTransaction {
     Tip(PendingTip),
     AccessFee(PendingAccessFee),
+    ReplayFee(PendingReplayFee),
+}
+
+impl PendingTransaction {
+    /// Room the transaction pays into
+    pub fn room_id(&self) -> &RoomId {
+        match self {
+            PendingTransaction::Tip(tip) => &tip.room_id,
+            PendingTransaction::AccessFee(fee) => &fee.room_id,
+            PendingTransaction::ReplayFee(fee) => &fee.room_id,
+        }
+    }
+
+    /// Amount paid by the transaction
+    pub fn amount(&self) -> Amount {
+        match self {
+            PendingTransaction::Tip(tip) => tip.amount,
+            PendingTransaction::AccessFee(fee) => fee.amount,
+            PendingTransaction::ReplayFee(fee) => fee.amount,
+        }
+    }
+}
+
+impl UserPaymentState {
+    /// Create an empty payment state with default preferences
+    pub fn new(user_id: UserId) -> Self {
+        Self {
+            user_id,
+            balance: Amount::ZERO,
+            pending_transactions: Vec::new(),
+            preferences: UserPaymentPreferences::default(),
+        }
+    }
+
+    /// Total of the transactions waiting for settlement
+    pub fn pending_amount(&self) -> Amount {
+        self.pending_transactions.iter()
+            .fold(Amount::ZERO, |total, tx| total.saturating_add(tx.amount()))
+    }
+
+    /// Whether enough payments are pending for them to be settled right away
+    pub fn should_auto_settle(&self) -> bool {
+        self.pending_amount() >= self.preferences.auto_settle_threshold
+            || self.pending_transactions.len() >= self.preferences.max_pending
+    }
+}
+
+impl RoomPaymentPool {
+    /// Create an open pool with no revenue yet
+    pub fn new(room_id: RoomId, host: UserId, settings: RoomPaymentSettings) -> Self {
+        Self {
+            room_id,
+            host,
+            tier: StakingTier::default(),
+            relays: Vec::new(),
+            total_tips: Amount::ZERO,
+            total_access_fees: Amount::ZERO,
+            available: Amount::ZERO,
+            withdrawn: Amount::ZERO,
+            gross_settled: Amount::ZERO,
+            platform_fees: Amount::ZERO,
+            relay_fees: Amount::ZERO,
+            pending_tips: Vec::new(),
+            pending_access_fees: Vec::new(),
+            pending_replay_fees: Vec::new(),
+            replay_prices: HashMap::new(),
+            settings,
+            is_open: true,
+            origin_chain: None,
+        }
+    }
+
+    /// Whether the room currently accepts tips and access fees
+    pub fn accepts_payments(&self) -> bool {
+        self.is_open && self.settings.payments_enabled
+    }
+
+    /// Remove the pending payments made by a user and return their total
+    pub fn take_pending_from(&mut self, user_id: &UserId) -> Amount {
+        let mut total = Amount::ZERO;
+        self.pending_tips.retain(|tip| {
+            let keep = tip.from != *user_id;
+            if !keep {
+                total = total.saturating_add(tip.amount);
+            }
+            keep
+        });
+        self.pending_access_fees.retain(|fee| {
+            let keep = fee.user_id != *user_id;
+            if !keep {
+                total = total.saturating_add(fee.amount);
+            }
+            keep
+        });
+        self.pending_replay_fees.retain(|fee| {
+            let keep = fee.user_id != *user_id;
+            if !keep {
+                total = total.saturating_add(fee.amount);
+            }
+            keep
+        });
+        total
+    }
+
+    /// Remove every pending payment and return their total
+    pub fn take_all_pending(&mut self) -> Amount {
+        let amounts = self.pending_tips.drain(..).map(|tip| tip.amount)
+            .chain(self.pending_access_fees.drain(..).map(|fee| fee.amount))
+            .chain(self.pending_replay_fees.drain(..).map(|fee| fee.amount));
+        amounts.fold(Amount::ZERO, Amount::saturating_add)
+    }
+}
+
+/// One user's payments to one room, settled together
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
+pub struct SettlementRecord {
+    pub user_id: UserId,
+    pub room_id: RoomId,
+    pub transaction_count: usize,
+    pub amount: Amount,
+    /// Hash of every transaction settled in the same batch, which may span several rooms
+    #[graphql(skip)]
+    pub settlement_hash: Hash,
+    pub timestamp: Timestamp,
+}
+
+/// One page of settlement records, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct SettlementPage {
+    pub settlements: Vec<SettlementRecord>,
+    /// Cursor to pass as `after` for the next page; `None` on the last page
+    pub next_cursor: Option<u64>,
 }
 
 /// Payment statistics
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, SimpleObject)]
 pub struct PaymentStats {
     /// Total payments processed
-    pub total_processed: u128,
+    pub total_processed: Amount,
     /// Total tips sent
-    pub total_tips: u128,
+    pub total_tips: Amount,
     /// Total access fees collected
-    pub total_access_fees: u128,
+    pub total_access_fees: Amount,
     /// Number of active users
     pub active_users: u64,
     /// Number of active payment pools
     pub active_rooms: u64,
+    /// Platform fees paid to the treasury
+    pub platform_fees: Amount,
 }
 
 /// Payment operation messages
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PaymentOperation {
+    /// Move tokens from the signer's account into their payment balance
+    Deposit {
+        amount: Amount,
+    },
+    /// Move tokens from the signer's payment balance back to their account
+    WithdrawBalance {
+        amount: Amount,
+    },
     /// Send a tip to a room
     SendTip {
         room_id: RoomId,
-        amount: u64,
+        amount: Amount,
         message: Option<String>,
         super_chat: bool,
     },
@@ -196,28 +521,57 @@ pub enum PaymentOperation {
         room_id: RoomId,
         quality_tier: StreamQuality,
     },
-    /// Settle user's pending transactions
+    /// Settle user's pending transactions; only the user may settle them
     SettlePendingTransactions {
         user_id: UserId,
     },
-    /// Create or update room payment settings
+    /// Open a payment pool for a room with the signer as host
+    ///
+    /// Rejected when a room manager is configured, since it opens pools for its rooms.
+    CreatePaymentPool {
+        room_id: RoomId,
+        settings: RoomPaymentSettings,
+    },
+    /// Settle all pending payments of a room and stop accepting new ones; host only
+    ClosePaymentPool {
+        room_id: RoomId,
+    },
+    /// Update room payment settings
     UpdateRoomSettings {
         room_id: RoomId,
         settings: RoomPaymentSettings,
     },
-    /// Withdraw funds for room host
+    /// Withdraw settled revenue to the host's account on this chain, or to `destination`
     WithdrawFunds {
         room_id: RoomId,
-        amount: u128,
+        amount: Amount,
+        destination: Option<Account>,
+    },
+    /// Buy replay access to a recording of a room
+    PayReplayAccess {
+        room_id: RoomId,
+        blob_id: BlobId,
+    },
+    /// Apply a room lifecycle change; only accepted from the room-manager application
+    ApplyRoomLifecycle {
+        event: RoomLifecycle,
     },
 }
 
 /// Payment operation result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PaymentResult {
+    Deposited {
+        user_id: UserId,
+        balance: Amount,
+    },
+    BalanceWithdrawn {
+        user_id: UserId,
+        balance: Amount,
+    },
     TipSent {
         tip_id: TipId,
-        amount: u64,
+        amount: Amount,
         pending_settlement: bool,
     },
     AccessFeePaid {
@@ -228,24 +582,41 @@ pub enum PaymentResult {
     TransactionsSettled {
         user_id: UserId,
         transaction_count: usize,
-        total_amount: u128,
+        total_amount: Amount,
         settlement_hash: Hash,
     },
+    PaymentPoolCreated {
+        room_id: RoomId,
+    },
+    PaymentPoolClosed {
+        room_id: RoomId,
+        settled_amount: Amount,
+    },
     RoomSettingsUpdated {
         room_id: RoomId,
     },
+    ReplayAccessPaid {
+        room_id: RoomId,
+        blob_id: BlobId,
+        pending_settlement: bool,
+    },
     FundsWithdrawn {
         room_id: RoomId,
-        amount: u128,
+        amount: Amount,
+        available: Amount,
         withdrawal_hash: Hash,
     },
+    RoomLifecycleApplied {
+        room_id: RoomId,
+        forwarded: bool,
+    },
     PaymentError {
         error: PaymentError,
     },
 }
 
 /// Payment error types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PaymentError {
     InsufficientBalance,
     InvalidAmount,
@@ -256,56 +627,141 @@ pub enum PaymentError {
     SettlementFailed,
     InvalidRoomSettings,
     Unauthorized,
+    RecordingNotFound,
+    RoomAlreadyExists,
+    RoomClosed,
+    ManagedByRoomManager,
+}
+
+impl std::fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaymentError::InsufficientBalance => write!(f, "Insufficient balance"),
+            PaymentError::InvalidAmount => write!(f, "Invalid amount"),
+            PaymentError::RoomNotFound => write!(f, "Room not found"),
+            PaymentError::UserNotFound => write!(f, "User not found"),
+            PaymentError::InvalidQualityTier => write!(f, "Invalid quality tier"),
+            PaymentError::PaymentsDisabled => write!(f, "Payments are disabled for this room"),
+            PaymentError::SettlementFailed => write!(f, "Settlement failed"),
+            PaymentError::InvalidRoomSettings => write!(f, "Invalid room settings"),
+            PaymentError::Unauthorized => write!(f, "Unauthorized"),
+            PaymentError::RecordingNotFound => write!(f, "Recording not found or not for sale"),
+            PaymentError::RoomAlreadyExists => write!(f, "Room already has a payment pool"),
+            PaymentError::RoomClosed => write!(f, "Room payment pool is closed"),
+            PaymentError::ManagedByRoomManager => write!(f, "Payment pools are opened and closed by the room manager"),
+        }
+    }
 }
 
 // Type aliases for clarity
 pub type RoomId = String;
 pub type UserId = String;
 pub type TipId = String;
+/// Walrus blob identifier of a recording
+pub type BlobId = String;
 pub type Timestamp = u64;
 pub type Hash = [u8; 32];
 
+/// Largest page returned by list queries
+pub const MAX_PAGE_SIZE: usize = 100;
+
 /// User payment summary for frontend display
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct UserPaymentSummary {
     pub user_id: UserId,
     pub room_id: RoomId,
-    pub balance: u128,
+    pub balance: Amount,
     pub pending_transactions: usize,
     pub pending_tips: usize,
     pub pending_access_fees: usize,
     pub can_afford_tips: bool,
-    pub recommended_tip: u64,
-    pub auto_settle_threshold: u128,
+    pub recommended_tip: Amount,
+    pub auto_settle_threshold: Amount,
 }
 
 /// Room revenue breakdown for streamer dashboard
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct RoomRevenueBreakdown {
     pub room_id: RoomId,
     pub host: UserId,
-    pub total_tips: u128,
-    pub total_access_fees: u128,
-    pub pending_tips: u128,
-    pub pending_access_fees: u128,
-    pub total_revenue: u128,
-    pub pending_revenue: u128,
+    pub total_tips: Amount,
+    pub total_access_fees: Amount,
+    pub pending_tips: Amount,
+    pub pending_access_fees: Amount,
+    pub total_revenue: Amount,
+    pub pending_revenue: Amount,
+    pub available: Amount,
+    pub withdrawn: Amount,
+    /// Staking tier the platform fee was charged at
+    pub tier: StakingTier,
+    /// Settled revenue before fees
+    pub gross: Amount,
+    pub platform_fees: Amount,
+    pub relay_fees: Amount,
+    /// Settled revenue left to the host after fees
+    pub net: Amount,
     pub active_tippers: usize,
-    pub quality_tier_revenue: HashMap<StreamQuality, u128>,
+    #[graphql(skip)]
+    pub quality_tier_revenue: HashMap<StreamQuality, Amount>,
+}
+
+#[ComplexObject]
+impl RoomPaymentPool {
+    /// Recordings sold for replay and their prices
+    async fn replay_prices(&self) -> Vec<ReplayPrice> {
+        let mut prices: Vec<ReplayPrice> = self.replay_prices.iter()
+            .map(|(blob_id, price)| ReplayPrice { blob_id: blob_id.clone(), price: *price })
+            .collect();
+        prices.sort_by(|a, b| a.blob_id.cmp(&b.blob_id));
+        prices
+    }
+}
+
+#[ComplexObject]
+impl RoomPaymentSettings {
+    /// Price per quality tier, from lowest to highest quality
+    async fn quality_tiers(&self) -> Vec<QualityPrice> {
+        let mut prices: Vec<QualityPrice> = self.quality_tiers.iter()
+            .map(|(quality, price)| QualityPrice { quality: *quality, price: *price })
+            .collect();
+        prices.sort_by_key(|price| price.quality);
+        prices
+    }
+}
+
+#[ComplexObject]
+impl SettlementRecord {
+    /// Settlement hash as a hex string
+    async fn settlement_hash(&self) -> String {
+        self.settlement_hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+#[ComplexObject]
+impl RoomRevenueBreakdown {
+    /// Pending access fee revenue per quality tier, from lowest to highest quality
+    async fn quality_tier_revenue(&self) -> Vec<QualityRevenue> {
+        let mut revenue: Vec<QualityRevenue> = self.quality_tier_revenue.iter()
+            .map(|(quality, amount)| QualityRevenue { quality: *quality, amount: *amount })
+            .collect();
+        revenue.sort_by_key(|entry| entry.quality);
+        revenue
+    }
 }
 
 
 impl Default for RoomPaymentSettings {
     fn default() -> Self {
         let mut quality_tiers = HashMap::new();
-        quality_tiers.insert(StreamQuality::Standard, 0);
-        quality_tiers.insert(StreamQuality::High, 100);
-        quality_tiers.insert(StreamQuality::Premium, 500);
-        quality_tiers.insert(StreamQuality::Ultra, 2000);
+        quality_tiers.insert(StreamQuality::Standard, Amount::ZERO);
+        quality_tiers.insert(StreamQuality::High, Amount::from_millis(100));
+        quality_tiers.insert(StreamQuality::Premium, Amount::from_millis(500));
+        quality_tiers.insert(StreamQuality::Ultra, Amount::from_tokens(2));
 
         Self {
-            min_tip: 1,
-            access_fee: 0,
+            min_tip: Amount::from_millis(1),
+            access_fee: Amount::ZERO,
             quality_tiers,
             payments_enabled: true,
         }
@@ -315,8 +771,8 @@ impl Default for RoomPaymentSettings {
 impl Default for UserPaymentPreferences {
     fn default() -> Self {
         Self {
-            default_tip: 100,
-            auto_settle_threshold: 10000,
+            default_tip: Amount::from_millis(100),
+            auto_settle_threshold: Amount::from_tokens(10),
             max_pending: 50,
         }
     }
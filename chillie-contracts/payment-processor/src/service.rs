@@ -1,18 +1,21 @@
 // Payment Processor Service - Linera SDK Implementation
-// Provides query interface for payment operations
+// Provides the GraphQL interface for payment queries and operations
 
 #![cfg_attr(target_arch = "wasm32", no_main)]
 
-use super::state;
+mod state;
 
 use std::sync::Arc;
 
-use crate::{
-    PaymentProcessorAbi, PaymentQuery, PaymentQueryResponse, PaymentError,
-    RoomId, UserId, UserPaymentSummary, RoomRevenueBreakdown,
+use async_graphql::{EmptySubscription, Object, Request, Response, Schema};
+use chillie_payment_processor::{
+    BlobId, PaymentError, PaymentOperation, PaymentParameters, PaymentProcessorAbi, PaymentStats, PendingAccessFee,
+    PendingTip, PendingTransaction, QualityPrice, RoomId, RoomPaymentPool, RoomPaymentSettings,
+    RoomRevenueBreakdown, SettlementPage, StreamQuality, UserId, UserPaymentState, UserPaymentSummary,
+    MAX_PAGE_SIZE,
 };
 use linera_sdk::{
-    linera_base_types::WithServiceAbi,
+    linera_base_types::{Account, Amount, WithServiceAbi},
     views::View,
     Service, ServiceRuntime,
 };
@@ -21,7 +24,7 @@ use self::state::PaymentProcessorState;
 use std::collections::HashMap;
 
 pub struct PaymentProcessorService {
-    state: PaymentProcessorState,
+    state: Arc<PaymentProcessorState>,
     runtime: Arc<ServiceRuntime<Self>>,
 }
 
@@ -32,143 +35,161 @@ impl WithServiceAbi for PaymentProcessorService {
 }
 
 impl Service for PaymentProcessorService {
-    type Parameters = ();
+    type Parameters = PaymentParameters;
 
     async fn new(runtime: ServiceRuntime<Self>) -> Self {
         let state = PaymentProcessorState::load(runtime.root_view_storage_context())
             .await
             .expect("Failed to load payment processor state");
         PaymentProcessorService {
-            state,
+            state: Arc::new(state),
             runtime: Arc::new(runtime),
         }
     }
 
-    async fn handle_query(&self, query: PaymentQuery) -> PaymentQueryResponse {
-        match query {
-            PaymentQuery::GetRoomStats { room_id } => {
-                match self.query_room_stats(&room_id) {
-                    Ok(room) => PaymentQueryResponse::RoomStats(Some(room.clone())),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-            PaymentQuery::GetUserState { user_id } => {
-                match self.query_user_state(&user_id) {
-                    Ok(user) => PaymentQueryResponse::UserState(Some(user.clone())),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-            PaymentQuery::GetGlobalStats => {
-                match self.query_global_stats() {
-                    Ok(stats) => PaymentQueryResponse::GlobalStats(stats.clone()),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-            PaymentQuery::GetPendingTips { room_id } => {
-                match self.query_pending_tips(&room_id) {
-                    Ok(tips) => PaymentQueryResponse::PendingTips(tips.clone()),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-            PaymentQuery::GetPendingAccessFees { room_id } => {
-                match self.query_pending_access_fees(&room_id) {
-                    Ok(fees) => PaymentQueryResponse::PendingAccessFees(fees.clone()),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-            PaymentQuery::GetUserPendingTransactions { user_id } => {
-                match self.query_user_pending_transactions(&user_id) {
-                    Ok(transactions) => PaymentQueryResponse::UserPendingTransactions(transactions.clone()),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-            PaymentQuery::GetQualityTierPricing { room_id } => {
-                match self.get_quality_tier_pricing(&room_id) {
-                    Ok(pricing) => PaymentQueryResponse::QualityTierPricing(pricing),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-            PaymentQuery::GetUserPaymentSummary { user_id, room_id } => {
-                match self.get_user_payment_summary(&user_id, &room_id) {
-                    Ok(summary) => PaymentQueryResponse::UserPaymentSummary(summary),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-            PaymentQuery::GetRoomRevenueBreakdown { room_id } => {
-                match self.get_room_revenue_breakdown(&room_id) {
-                    Ok(breakdown) => PaymentQueryResponse::RoomRevenueBreakdown(breakdown),
-                    Err(e) => PaymentQueryResponse::Error(e),
-                }
-            }
-        }
+    async fn handle_query(&self, request: Request) -> Response {
+        let schema = Schema::build(
+            QueryRoot { state: self.state.clone() },
+            MutationRoot { runtime: self.runtime.clone() },
+            EmptySubscription,
+        )
+        .finish();
+        schema.execute(request).await
     }
 }
 
-impl PaymentProcessorService {
-    /// Query room payment statistics
-    pub fn query_room_stats(
-        &self,
-        room_id: &RoomId,
-    ) -> Result<&crate::RoomPaymentPool, PaymentError> {
-        self.state.get_room_stats(room_id)
-            .ok_or(PaymentError::RoomNotFound)
+struct QueryRoot {
+    state: Arc<PaymentProcessorState>,
+}
+
+#[Object]
+impl QueryRoot {
+    /// Payment pool of a room
+    async fn room_stats(&self, room_id: RoomId) -> async_graphql::Result<RoomPaymentPool> {
+        Ok(self.room(&room_id).await?)
     }
 
-    /// Query user payment state
-    pub fn query_user_state(
+    /// Balance, pending transactions and preferences of a user
+    async fn user_state(&self, user_id: UserId) -> async_graphql::Result<UserPaymentState> {
+        Ok(self.user(&user_id).await?)
+    }
+
+    /// Global payment statistics
+    async fn global_stats(&self) -> PaymentStats {
+        self.state.get_global_stats().clone()
+    }
+
+    /// Tips waiting for settlement in a room
+    async fn pending_tips(&self, room_id: RoomId) -> async_graphql::Result<Vec<PendingTip>> {
+        Ok(self.room(&room_id).await?.pending_tips)
+    }
+
+    /// Access fees waiting for settlement in a room
+    async fn pending_access_fees(&self, room_id: RoomId) -> async_graphql::Result<Vec<PendingAccessFee>> {
+        Ok(self.room(&room_id).await?.pending_access_fees)
+    }
+
+    /// Transactions a user has not settled yet
+    async fn user_pending_transactions(&self, user_id: UserId) -> async_graphql::Result<Vec<PendingTransaction>> {
+        Ok(self.user(&user_id).await?.pending_transactions)
+    }
+
+    /// Price per quality tier of a room, from lowest to highest quality
+    async fn quality_tier_pricing(&self, room_id: RoomId) -> async_graphql::Result<Vec<QualityPrice>> {
+        let pricing = self.get_quality_tier_pricing(&room_id).await?;
+        let mut prices: Vec<QualityPrice> = pricing.into_iter()
+            .map(|(quality, price)| QualityPrice { quality, price })
+            .collect();
+        prices.sort_by_key(|price| price.quality);
+        Ok(prices)
+    }
+
+    /// Recommended tip for a user in a room
+    async fn recommended_tip(&self, user_id: UserId, room_id: RoomId) -> async_graphql::Result<Amount> {
+        Ok(self.calculate_recommended_tip(&user_id, &room_id).await?)
+    }
+
+    /// Whether a user's balance covers a quality tier of a room
+    async fn can_afford_quality_tier(
         &self,
-        user_id: &UserId,
-    ) -> Result<&crate::UserPaymentState, PaymentError> {
-        self.state.get_user_state(user_id)
-            .ok_or(PaymentError::UserNotFound)
+        user_id: UserId,
+        room_id: RoomId,
+        quality_tier: StreamQuality,
+    ) -> async_graphql::Result<bool> {
+        Ok(self.check_quality_tier_affordable(&user_id, &room_id, &quality_tier).await?)
     }
 
-    /// Query global payment statistics
-    pub fn query_global_stats(&self) -> Result<&crate::PaymentStats, PaymentError> {
-        Ok(self.state.get_global_stats())
+    /// Whether a user's pending transactions should be settled now
+    async fn should_auto_settle(&self, user_id: UserId) -> async_graphql::Result<bool> {
+        Ok(self.check_auto_settle(&user_id).await?)
     }
 
-    /// Query pending tips for a room
-    pub fn query_pending_tips(
+    /// Payment summary for a user in a room
+    async fn user_payment_summary(
         &self,
-        room_id: &RoomId,
-    ) -> Result<&Vec<crate::PendingTip>, PaymentError> {
-        let room = self.state.get_room_stats(room_id)
-            .ok_or(PaymentError::RoomNotFound)?;
-        Ok(&room.pending_tips)
+        user_id: UserId,
+        room_id: RoomId,
+    ) -> async_graphql::Result<UserPaymentSummary> {
+        Ok(self.get_user_payment_summary(&user_id, &room_id).await?)
     }
 
-    /// Query pending access fees for a room
-    pub fn query_pending_access_fees(
+    /// Settlements of a user's payments, oldest first
+    async fn user_settlements(
         &self,
-        room_id: &RoomId,
-    ) -> Result<&Vec<crate::PendingAccessFee>, PaymentError> {
-        let room = self.state.get_room_stats(room_id)
-            .ok_or(PaymentError::RoomNotFound)?;
-        Ok(&room.pending_access_fees)
+        user_id: UserId,
+        after: Option<u64>,
+        #[graphql(default = 20)] limit: usize,
+    ) -> SettlementPage {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        self.state.user_settlements_page(&user_id, after, limit).await.expect("Failed to get settlements")
     }
 
-    /// Query user's pending transactions
-    pub fn query_user_pending_transactions(
+    /// Settlements of payments to a room, oldest first
+    async fn room_settlements(
         &self,
-        user_id: &UserId,
-    ) -> Result<&Vec<crate::PendingTransaction>, PaymentError> {
-        let user_state = self.state.get_user_state(user_id)
-            .ok_or(PaymentError::UserNotFound)?;
-        Ok(&user_state.pending_transactions)
+        room_id: RoomId,
+        after: Option<u64>,
+        #[graphql(default = 20)] limit: usize,
+    ) -> SettlementPage {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        self.state.room_settlements_page(&room_id, after, limit).await.expect("Failed to get settlements")
+    }
+
+    /// Whether a user bought replay access to a room recording
+    async fn has_replay_access(&self, room_id: RoomId, blob_id: BlobId, user_id: UserId) -> bool {
+        self.state.replay_access.contains_key(&(room_id, blob_id, user_id)).await
+            .expect("Failed to get replay access")
+    }
+
+    /// Revenue breakdown of a room for the streamer dashboard
+    async fn room_revenue_breakdown(&self, room_id: RoomId) -> async_graphql::Result<RoomRevenueBreakdown> {
+        Ok(self.get_room_revenue_breakdown(&room_id).await?)
+    }
+}
+
+impl QueryRoot {
+    /// Query room payment pool
+    async fn room(&self, room_id: &RoomId) -> Result<RoomPaymentPool, PaymentError> {
+        self.state.get_room_stats(room_id).await
+            .expect("Failed to get room")
+            .ok_or(PaymentError::RoomNotFound)
+    }
+
+    /// Query user payment state
+    async fn user(&self, user_id: &UserId) -> Result<UserPaymentState, PaymentError> {
+        self.state.get_user_state(user_id).await
+            .expect("Failed to get user")
+            .ok_or(PaymentError::UserNotFound)
     }
 
     /// Calculate recommended tip amount based on user history
-    pub fn calculate_recommended_tip(
+    async fn calculate_recommended_tip(
         &self,
         user_id: &UserId,
         room_id: &RoomId,
-    ) -> Result<u64, PaymentError> {
-        let user_state = self.state.get_user_state(user_id)
-            .ok_or(PaymentError::UserNotFound)?;
-
-        let room_stats = self.state.get_room_stats(room_id)
-            .ok_or(PaymentError::RoomNotFound)?;
+    ) -> Result<Amount, PaymentError> {
+        let user_state = self.user(user_id).await?;
+        let room_stats = self.room(room_id).await?;
 
         // Calculate recommendation based on user default and room minimum
         let base_recommendation = user_state.preferences.default_tip;
@@ -178,46 +199,39 @@ impl PaymentProcessorService {
     }
 
     /// Get quality tier pricing for a room
-    pub fn get_quality_tier_pricing(
+    async fn get_quality_tier_pricing(
         &self,
         room_id: &RoomId,
-    ) -> Result<HashMap<crate::StreamQuality, u64>, PaymentError> {
-        let room_stats = self.state.get_room_stats(room_id)
-            .ok_or(PaymentError::RoomNotFound)?;
+    ) -> Result<HashMap<StreamQuality, Amount>, PaymentError> {
+        let room_stats = self.room(room_id).await?;
 
-        Ok(room_stats.settings.quality_tiers.clone())
+        Ok(room_stats.settings.quality_tiers)
     }
 
     /// Check if user can afford a specific quality tier
-    pub fn can_afford_quality_tier(
+    async fn check_quality_tier_affordable(
         &self,
         user_id: &UserId,
         room_id: &RoomId,
-        quality_tier: &crate::StreamQuality,
+        quality_tier: &StreamQuality,
     ) -> Result<bool, PaymentError> {
-        let user_state = self.state.get_user_state(user_id)
-            .ok_or(PaymentError::UserNotFound)?;
-
-        let room_stats = self.state.get_room_stats(room_id)
-            .ok_or(PaymentError::RoomNotFound)?;
+        let user_state = self.user(user_id).await?;
+        let room_stats = self.room(room_id).await?;
 
         let cost = room_stats.settings.quality_tiers.get(quality_tier)
             .ok_or(PaymentError::InvalidQualityTier)?;
 
-        Ok(user_state.balance >= *cost as u128)
+        Ok(user_state.balance >= *cost)
     }
 
     /// Get payment summary for a user in a room
-    pub fn get_user_payment_summary(
+    async fn get_user_payment_summary(
         &self,
         user_id: &UserId,
         room_id: &RoomId,
     ) -> Result<UserPaymentSummary, PaymentError> {
-        let user_state = self.state.get_user_state(user_id)
-            .ok_or(PaymentError::UserNotFound)?;
-
-        let room_stats = self.state.get_room_stats(room_id)
-            .ok_or(PaymentError::RoomNotFound)?;
+        let user_state = self.user(user_id).await?;
+        let room_stats = self.room(room_id).await?;
 
         // Count user's pending transactions in this room
         let pending_tips_count = room_stats.pending_tips.iter()
@@ -235,39 +249,29 @@ impl PaymentProcessorService {
             pending_transactions: user_state.pending_transactions.len(),
             pending_tips: pending_tips_count,
             pending_access_fees: pending_access_fees_count,
-            can_afford_tips: user_state.balance > room_stats.settings.min_tip as u128,
+            can_afford_tips: user_state.balance >= room_stats.settings.min_tip,
             recommended_tip: user_state.preferences.default_tip,
             auto_settle_threshold: user_state.preferences.auto_settle_threshold,
         })
     }
 
     /// Check if user should auto-settle transactions
-    pub fn should_auto_settle(&self, user_id: &UserId) -> Result<bool, PaymentError> {
-        let user_state = self.state.get_user_state(user_id)
-            .ok_or(PaymentError::UserNotFound)?;
-
-        let pending_count = user_state.pending_transactions.len();
-        let threshold_reached = user_state.balance >= user_state.preferences.auto_settle_threshold;
-        let max_pending_reached = pending_count >= user_state.preferences.max_pending;
-
-        Ok(threshold_reached || max_pending_reached)
+    async fn check_auto_settle(&self, user_id: &UserId) -> Result<bool, PaymentError> {
+        Ok(self.user(user_id).await?.should_auto_settle())
     }
 
     /// Get room revenue breakdown
-    pub fn get_room_revenue_breakdown(
+    async fn get_room_revenue_breakdown(
         &self,
         room_id: &RoomId,
     ) -> Result<RoomRevenueBreakdown, PaymentError> {
-        let room_stats = self.state.get_room_stats(room_id)
-            .ok_or(PaymentError::RoomNotFound)?;
+        let room_stats = self.room(room_id).await?;
 
-        let total_pending_tips: u128 = room_stats.pending_tips.iter()
-            .map(|tip| tip.amount as u128)
-            .sum();
+        let total_pending_tips = room_stats.pending_tips.iter()
+            .fold(Amount::ZERO, |total, tip| total.saturating_add(tip.amount));
 
-        let total_pending_access_fees: u128 = room_stats.pending_access_fees.iter()
-            .map(|fee| fee.amount as u128)
-            .sum();
+        let total_pending_access_fees = room_stats.pending_access_fees.iter()
+            .fold(Amount::ZERO, |total, fee| total.saturating_add(fee.amount));
 
         Ok(RoomRevenueBreakdown {
             room_id: room_id.clone(),
@@ -276,27 +280,137 @@ impl PaymentProcessorService {
             total_access_fees: room_stats.total_access_fees,
             pending_tips: total_pending_tips,
             pending_access_fees: total_pending_access_fees,
-            total_revenue: room_stats.total_tips + room_stats.total_access_fees,
-            pending_revenue: total_pending_tips + total_pending_access_fees,
+            total_revenue: room_stats.total_tips.saturating_add(room_stats.total_access_fees),
+            pending_revenue: total_pending_tips.saturating_add(total_pending_access_fees),
+            available: room_stats.available,
+            withdrawn: room_stats.withdrawn,
+            tier: room_stats.tier,
+            gross: room_stats.gross_settled,
+            platform_fees: room_stats.platform_fees,
+            relay_fees: room_stats.relay_fees,
+            net: room_stats.gross_settled
+                .saturating_sub(room_stats.platform_fees)
+                .saturating_sub(room_stats.relay_fees),
             active_tippers: room_stats.pending_tips.iter()
                 .map(|tip| tip.from.clone())
                 .collect::<std::collections::HashSet<_>>()
                 .len(),
-            quality_tier_revenue: self.calculate_quality_tier_revenue(&room_stats),
+            quality_tier_revenue: Self::calculate_quality_tier_revenue(&room_stats),
         })
     }
 
     /// Calculate revenue breakdown by quality tier
     fn calculate_quality_tier_revenue(
-        &self,
-        room_stats: &crate::RoomPaymentPool,
-    ) -> HashMap<crate::StreamQuality, u128> {
+        room_stats: &RoomPaymentPool,
+    ) -> HashMap<StreamQuality, Amount> {
         let mut revenue_by_tier = HashMap::new();
 
         for access_fee in &room_stats.pending_access_fees {
-            *revenue_by_tier.entry(access_fee.quality_tier).or_insert(0) += access_fee.amount as u128;
+            let revenue = revenue_by_tier.entry(access_fee.quality_tier).or_insert(Amount::ZERO);
+            *revenue = revenue.saturating_add(access_fee.amount);
         }
 
         revenue_by_tier
     }
-}
\ No newline at end of file
+}
+
+/// Helpers that schedule payment operations in the next block proposed by the client
+struct MutationRoot {
+    runtime: Arc<ServiceRuntime<PaymentProcessorService>>,
+}
+
+#[Object]
+impl MutationRoot {
+    /// Deposit tokens from the signer's account into their payment balance
+    async fn deposit(&self, amount: Amount) -> bool {
+        self.runtime.schedule_operation(&PaymentOperation::Deposit { amount });
+        true
+    }
+
+    /// Withdraw tokens from the signer's payment balance to their account
+    async fn withdraw_balance(&self, amount: Amount) -> bool {
+        self.runtime.schedule_operation(&PaymentOperation::WithdrawBalance { amount });
+        true
+    }
+
+    /// Send a tip to a room
+    async fn send_tip(
+        &self,
+        room_id: RoomId,
+        amount: Amount,
+        message: Option<String>,
+        #[graphql(default)] super_chat: bool,
+    ) -> bool {
+        self.runtime.schedule_operation(&PaymentOperation::SendTip { room_id, amount, message, super_chat });
+        true
+    }
+
+    /// Pay the access fee of a quality tier
+    async fn pay_access_fee(&self, room_id: RoomId, quality_tier: StreamQuality) -> bool {
+        self.runtime.schedule_operation(&PaymentOperation::PayAccessFee { room_id, quality_tier });
+        true
+    }
+
+    /// Settle the signer's pending transactions; `user_id` must be the signer
+    async fn settle_pending_transactions(&self, user_id: UserId) -> bool {
+        self.runtime.schedule_operation(&PaymentOperation::SettlePendingTransactions { user_id });
+        true
+    }
+
+    /// Open a payment pool for a room with the signer as host
+    async fn create_payment_pool(
+        &self,
+        room_id: RoomId,
+        min_tip: Amount,
+        access_fee: Amount,
+        quality_tiers: Vec<QualityPrice>,
+    ) -> bool {
+        let settings = RoomPaymentSettings {
+            min_tip,
+            access_fee,
+            quality_tiers: quality_tiers.into_iter().map(|tier| (tier.quality, tier.price)).collect(),
+            payments_enabled: true,
+        };
+        self.runtime.schedule_operation(&PaymentOperation::CreatePaymentPool { room_id, settings });
+        true
+    }
+
+    /// Settle a room's pending payments and close its payment pool
+    async fn close_payment_pool(&self, room_id: RoomId) -> bool {
+        self.runtime.schedule_operation(&PaymentOperation::ClosePaymentPool { room_id });
+        true
+    }
+
+    /// Replace a room's payment settings
+    async fn update_room_settings(
+        &self,
+        room_id: RoomId,
+        min_tip: Amount,
+        access_fee: Amount,
+        quality_tiers: Vec<QualityPrice>,
+        payments_enabled: bool,
+    ) -> bool {
+        let settings = RoomPaymentSettings {
+            min_tip,
+            access_fee,
+            quality_tiers: quality_tiers.into_iter().map(|tier| (tier.quality, tier.price)).collect(),
+            payments_enabled,
+        };
+        self.runtime.schedule_operation(&PaymentOperation::UpdateRoomSettings { room_id, settings });
+        true
+    }
+
+    /// Buy replay access to a room recording
+    async fn pay_replay_access(&self, room_id: RoomId, blob_id: BlobId) -> bool {
+        self.runtime.schedule_operation(&PaymentOperation::PayReplayAccess { room_id, blob_id });
+        true
+    }
+
+    /// Withdraw settled revenue for the room host
+    ///
+    /// Funds go to the host's account on this chain unless a `destination` is given.
+    async fn withdraw_funds(&self, room_id: RoomId, amount: Amount, destination: Option<Account>) -> bool {
+        self.runtime.schedule_operation(&PaymentOperation::WithdrawFunds { room_id, amount, destination });
+        true
+    }
+}
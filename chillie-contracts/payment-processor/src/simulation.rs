@@ -1,7 +1,8 @@
 // Chillie Payment Processor Use Case Simulation
 // Demonstrates complete payment flow: Host creates room → Participants join & tip → Settlement
 
-use crate::{StreamQuality, PaymentError, RoomPaymentSettings};
+use chillie_payment_processor::{StreamQuality, PaymentError, RoomPaymentSettings};
+use linera_sdk::linera_base_types::Amount;
 use std::collections::HashMap;
 
 /// Complete use case simulation of the micropayment framework
@@ -48,26 +49,26 @@ impl PaymentSimulation {
 
         // Host sets up payment-friendly room with quality tiers
         let payment_settings = RoomPaymentSettings {
-            min_tip: 50,              // Minimum tip: 50 units
-            access_fee: 0,            // Public room (free entry)
+            min_tip: Amount::from_attos(50),              // Minimum tip: 50 units
+            access_fee: Amount::ZERO,                     // Public room (free entry)
             quality_tiers: {
                 let mut tiers = HashMap::new();
-                tiers.insert(StreamQuality::Standard, 0);   // SD: Free
-                tiers.insert(StreamQuality::High, 200);      // HD: 200 units
-                tiers.insert(StreamQuality::Premium, 500);  // FHD: 500 units
-                tiers.insert(StreamQuality::Ultra, 2000);    // 4K: 2000 units
+                tiers.insert(StreamQuality::Standard, Amount::ZERO);          // SD: Free
+                tiers.insert(StreamQuality::High, Amount::from_attos(200));    // HD: 200 units
+                tiers.insert(StreamQuality::Premium, Amount::from_attos(500)); // FHD: 500 units
+                tiers.insert(StreamQuality::Ultra, Amount::from_attos(2000));  // 4K: 2000 units
                 tiers
             },
             payments_enabled: true,
         };
 
-        println!("   • Minimum tip: {} units", payment_settings.min_tip);
-        println!("   • Access fee: {} units (public room)", payment_settings.access_fee);
+        println!("   • Minimum tip: {} units", u128::from(payment_settings.min_tip));
+        println!("   • Access fee: {} units (public room)", u128::from(payment_settings.access_fee));
         println!("   • Quality tiers configured:");
         println!("     - SD (Standard): Free");
-        println!("     - HD: {} units", payment_settings.quality_tiers[&StreamQuality::High]);
-        println!("     - FHD: {} units", payment_settings.quality_tiers[&StreamQuality::Premium]);
-        println!("     - 4K: {} units", payment_settings.quality_tiers[&StreamQuality::Ultra]);
+        println!("     - HD: {} units", u128::from(payment_settings.quality_tiers[&StreamQuality::High]));
+        println!("     - FHD: {} units", u128::from(payment_settings.quality_tiers[&StreamQuality::Premium]));
+        println!("     - 4K: {} units", u128::from(payment_settings.quality_tiers[&StreamQuality::Ultra]));
         println!("   • Payments: ✅ Enabled");
 
         println!("\n🚀 Room {} is now LIVE and ready for participants!", room_id);
@@ -2,12 +2,15 @@
 // Uses Linera's view system for persistent storage
 
 use linera_sdk::views::{
-    linera_views, MapView, RegisterView, RootView, ViewStorageContext,
+    linera_views, LogView, MapView, RegisterView, RootView, ViewError, ViewStorageContext,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap as StdHashMap;
 
-use super::*;
+use chillie_payment_processor::{
+    BlobId, PaymentStats, RoomId, RoomPaymentPool, SettlementPage, SettlementRecord, Timestamp, UserId,
+    UserPaymentState,
+};
 
 /// The application state for payment processor
 #[derive(RootView)]
@@ -19,6 +22,18 @@ pub struct PaymentProcessorState {
     pub users: MapView<UserId, UserPaymentState>,
     /// Global payment statistics
     pub stats: RegisterView<PaymentStats>,
+    /// Time each user bought replay access to a room recording
+    pub replay_access: MapView<(RoomId, BlobId, UserId), Timestamp>,
+    /// Ledger of settlements, oldest first
+    pub settlements: LogView<SettlementRecord>,
+    /// Number of settlement records of each user
+    pub user_settlement_counts: MapView<UserId, u64>,
+    /// Position in `settlements` of each user's records, keyed by their sequence number for the user
+    pub user_settlements: MapView<(UserId, u64), u64>,
+    /// Number of settlement records of each room
+    pub room_settlement_counts: MapView<RoomId, u64>,
+    /// Position in `settlements` of each room's records, keyed by their sequence number for the room
+    pub room_settlements: MapView<(RoomId, u64), u64>,
 }
 
 
@@ -40,12 +55,12 @@ impl PaymentProcessorState {
 
     /// Insert or update room payment pool (optimized with MapView)
     pub async fn insert_room(&mut self, room_id: RoomId, pool: RoomPaymentPool) -> Result<(), linera_sdk::views::ViewError> {
-        self.rooms.insert(&room_id, pool).await
+        self.rooms.insert(&room_id, pool)
     }
 
     /// Insert or update user state (optimized with MapView)
     pub async fn insert_user(&mut self, user_id: UserId, state: UserPaymentState) -> Result<(), linera_sdk::views::ViewError> {
-        self.users.insert(&user_id, state).await
+        self.users.insert(&user_id, state)
     }
 
     /// Update global statistics
@@ -65,11 +80,77 @@ impl PaymentProcessorState {
 
     /// Remove room
     pub async fn remove_room(&mut self, room_id: &RoomId) -> Result<(), linera_sdk::views::ViewError> {
-        self.rooms.remove(room_id).await
+        self.rooms.remove(room_id)
+    }
+
+    /// Append a settlement to the ledger and index it by user and room
+    pub async fn record_settlement(&mut self, record: SettlementRecord) -> Result<(), ViewError> {
+        let index = self.settlements.count() as u64;
+        let user_seq = self.user_settlement_counts.get(&record.user_id).await?.unwrap_or_default();
+        self.user_settlements.insert(&(record.user_id.clone(), user_seq), index)?;
+        self.user_settlement_counts.insert(&record.user_id, user_seq + 1)?;
+        let room_seq = self.room_settlement_counts.get(&record.room_id).await?.unwrap_or_default();
+        self.room_settlements.insert(&(record.room_id.clone(), room_seq), index)?;
+        self.room_settlement_counts.insert(&record.room_id, room_seq + 1)?;
+        self.settlements.push(record);
+        Ok(())
+    }
+
+    /// One page of a user's settlement records, oldest first
+    pub async fn user_settlements_page(
+        &self,
+        user_id: &UserId,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<SettlementPage, ViewError> {
+        let count = self.user_settlement_counts.get(user_id).await?.unwrap_or_default();
+        let mut indices = Vec::new();
+        for seq in page_range(count, after, limit) {
+            indices.extend(self.user_settlements.get(&(user_id.clone(), seq)).await?);
+        }
+        self.settlements_page(count, after, indices).await
+    }
+
+    /// One page of a room's settlement records, oldest first
+    pub async fn room_settlements_page(
+        &self,
+        room_id: &RoomId,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<SettlementPage, ViewError> {
+        let count = self.room_settlement_counts.get(room_id).await?.unwrap_or_default();
+        let mut indices = Vec::new();
+        for seq in page_range(count, after, limit) {
+            indices.extend(self.room_settlements.get(&(room_id.clone(), seq)).await?);
+        }
+        self.settlements_page(count, after, indices).await
+    }
+
+    /// Loads the records at the given ledger positions as the page following `after`
+    async fn settlements_page(
+        &self,
+        count: u64,
+        after: Option<u64>,
+        indices: Vec<u64>,
+    ) -> Result<SettlementPage, ViewError> {
+        let start = after.map_or(0, |after| after.saturating_add(1));
+        let end = start.saturating_add(indices.len() as u64);
+        let indices = indices.into_iter().map(|index| index as usize).collect();
+        let settlements = self.settlements.multi_get(indices).await?.into_iter().flatten().collect();
+        Ok(SettlementPage {
+            settlements,
+            next_cursor: (end > start && end < count).then(|| end - 1),
+        })
     }
 
     /// Remove user
     pub async fn remove_user(&mut self, user_id: &UserId) -> Result<(), linera_sdk::views::ViewError> {
-        self.users.remove(user_id).await
+        self.users.remove(user_id)
     }
-}
\ No newline at end of file
+}
+
+/// Sequence numbers of the page following `after` among `count` records
+fn page_range(count: u64, after: Option<u64>, limit: usize) -> std::ops::Range<u64> {
+    let start = after.map_or(0, |after| after.saturating_add(1)).min(count);
+    start..start.saturating_add(limit as u64).min(count)
+}
@@ -0,0 +1,84 @@
+use chillie_payment_processor::{
+    FeeSchedule, PendingAccessFee, PendingReplayFee, PendingTip, RoomPaymentPool, RoomPaymentSettings, StakingTier,
+    StreamQuality,
+};
+use linera_sdk::linera_base_types::Amount;
+
+fn fee_schedule(relay_share: u8) -> FeeSchedule {
+    FeeSchedule { relay_share, ..FeeSchedule::default() }
+}
+
+fn tip(from: &str, amount: Amount) -> PendingTip {
+    PendingTip {
+        room_id: "room".to_string(),
+        from: from.to_string(),
+        amount,
+        message: None,
+        timestamp: 0,
+        super_chat: false,
+    }
+}
+
+#[test]
+fn test_split_takes_the_tier_fee() {
+    let split = fee_schedule(0).split(StakingTier::Basic, Amount::from_tokens(100), 0);
+    assert_eq!(split.platform, Amount::from_tokens(30));
+    assert_eq!(split.host, Amount::from_tokens(70));
+    assert_eq!(split.per_relay, Amount::ZERO);
+
+    let split = fee_schedule(0).split(StakingTier::MultiStream, Amount::from_tokens(100), 0);
+    assert_eq!(split.platform, Amount::from_tokens(3));
+    assert_eq!(split.host, Amount::from_tokens(97));
+}
+
+#[test]
+fn test_split_gives_rounding_dust_to_the_host() {
+    let gross = Amount::from_tokens(100);
+    let split = fee_schedule(10).split(StakingTier::Basic, gross, 3);
+
+    assert_eq!(split.platform, Amount::from_tokens(30));
+    assert_eq!(split.per_relay, Amount::from_attos(3_333_333_333_333_333_333));
+    assert_eq!(split.host, Amount::from_attos(60_000_000_000_000_000_001));
+
+    let total = split.host
+        .saturating_add(split.platform)
+        .saturating_add(split.per_relay.saturating_mul(3));
+    assert_eq!(total, gross);
+}
+
+#[test]
+fn test_split_without_relays_keeps_the_relay_share_with_the_host() {
+    let split = fee_schedule(10).split(StakingTier::Basic, Amount::from_tokens(100), 0);
+    assert_eq!(split.per_relay, Amount::ZERO);
+    assert_eq!(split.host, Amount::from_tokens(70));
+}
+
+#[test]
+fn test_take_pending_from_only_takes_the_users_payments() {
+    let mut pool = RoomPaymentPool::new("room".to_string(), "host".to_string(), RoomPaymentSettings::default());
+    pool.pending_tips.push(tip("alice", Amount::from_tokens(1)));
+    pool.pending_tips.push(tip("bob", Amount::from_tokens(2)));
+    pool.pending_access_fees.push(PendingAccessFee {
+        room_id: "room".to_string(),
+        user_id: "alice".to_string(),
+        amount: Amount::from_millis(500),
+        quality_tier: StreamQuality::Premium,
+        timestamp: 0,
+    });
+    pool.pending_replay_fees.push(PendingReplayFee {
+        room_id: "room".to_string(),
+        user_id: "alice".to_string(),
+        blob_id: "blob".to_string(),
+        amount: Amount::from_millis(250),
+        timestamp: 0,
+    });
+
+    assert_eq!(pool.take_pending_from(&"alice".to_string()), Amount::from_millis(1750));
+    assert_eq!(pool.pending_tips.len(), 1);
+    assert_eq!(pool.pending_tips[0].from, "bob");
+    assert!(pool.pending_access_fees.is_empty());
+    assert!(pool.pending_replay_fees.is_empty());
+
+    assert_eq!(pool.take_pending_from(&"alice".to_string()), Amount::ZERO);
+    assert_eq!(pool.take_all_pending(), Amount::from_tokens(2));
+}
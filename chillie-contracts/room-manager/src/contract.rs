@@ -5,15 +5,27 @@
 mod state;
 
 use chillie::{
-    ChillieAbi, ChillieOperation, ChillieResponse, RoomId, RoomType, UserId, SupportedToken,
+    is_valid_blob_id, is_valid_node_address, BlobId, ChillieAbi, ChillieError, ChillieEvent, ChillieMessage,
+    ChillieOperation, ChillieParameters, ChillieResponse, FollowedRoomView, InstantiationArgument, Recording,
+    RewardConfig, RoomId, RoomMetadata, RoomRole, RoomType, SlashingConfig, StakingTier, SupportedToken, TierConfig,
+    UserId, MAX_PAGE_SIZE, MAX_REPUTATION, ROOM_EVENTS_STREAM, UNBONDING_PERIOD_SECS,
 };
+use chillie_payment_processor::{PaymentOperation, PaymentProcessorAbi, PaymentResult, RoomLifecycle};
 use linera_sdk::{
-    linera_base_types::{WithContractAbi, Amount},
+    abis::fungible::{FungibleOperation, FungibleTokenAbi},
+    linera_base_types::{
+        Account, AccountOwner, Amount, ApplicationPermissions, ChainId, ChainOwnership, StreamName, StreamUpdate,
+        TimeDelta, TimeoutConfig, Timestamp, WithContractAbi,
+    },
     views::{RootView, View},
     Contract, ContractRuntime,
 };
 
-use self::state::{ChillieRoomState, UserState, RoomState, NodeState};
+use std::collections::BTreeMap;
+
+use self::state::{
+    indices_after, ChillieRoomState, UserState, RoomState, NodeState, NodeExit, NodeReport, PendingWithdrawal,
+};
 
 pub struct ChillieContract {
     state: ChillieRoomState,
@@ -27,10 +39,10 @@ impl WithContractAbi for ChillieContract {
 }
 
 impl Contract for ChillieContract {
-    type Message = ();
-    type InstantiationArgument = ();
-    type Parameters = ();
-    type EventValue = ();
+    type Message = ChillieMessage;
+    type InstantiationArgument = InstantiationArgument;
+    type Parameters = ChillieParameters;
+    type EventValue = ChillieEvent;
 
     async fn load(runtime: ContractRuntime<Self>) -> Self {
         let state = ChillieRoomState::load(runtime.root_view_storage_context())
@@ -39,27 +51,126 @@ impl Contract for ChillieContract {
         ChillieContract { state, runtime }
     }
 
-    async fn instantiate(&mut self, _argument: ()) {
+    async fn instantiate(&mut self, argument: InstantiationArgument) {
         // Validate that the application parameters were configured correctly.
         self.runtime.application_parameters();
-        
-        // Initialize with Native token by default
-        self.state.staking_token.set(SupportedToken::Native);
+
+        if !argument.tier_config.is_valid() {
+            panic!("Tier thresholds must not decrease from one tier to the next");
+        }
+        if !argument.slashing_config.is_valid() {
+            panic!("Slashing parameters are out of bounds");
+        }
+        if !argument.reward_config.is_valid() {
+            panic!("Reward epochs must have a length");
+        }
+
+        // Without an explicit admin, the creator administers the configuration
+        let admin = argument.admin.or_else(|| self.runtime.authenticated_signer());
+        self.state.admin.set(admin.map(|owner| owner.to_string()));
+        self.state.staking_token.set(argument.staking_token);
+        self.state.tier_config.set(argument.tier_config);
+        self.state.slashing_config.set(argument.slashing_config);
+
+        let epoch = self.current_epoch(&argument.reward_config);
+        self.state.reward_config.set(argument.reward_config);
+        self.state.last_reward_epoch.set(epoch);
     }
 
     async fn execute_operation(&mut self, operation: ChillieOperation) -> ChillieResponse {
-        match operation {
+        let result = match operation {
             ChillieOperation::Stake { amount } => self.do_stake(amount).await,
             ChillieOperation::Unstake { amount } => self.do_unstake(amount).await,
-            ChillieOperation::CreateRoom { room_id, room_type } => self.do_create_room(room_id, room_type).await,
+            ChillieOperation::ClaimUnstaked => self.do_claim_unstaked().await,
+            ChillieOperation::CreateRoom { room_id, room_type, dedicated_chain, starts_at, ends_at, metadata } => {
+                self.do_create_room(room_id, room_type, dedicated_chain, starts_at, ends_at, metadata).await
+            }
             ChillieOperation::CloseRoom { room_id } => self.do_close_room(room_id).await,
-            ChillieOperation::RegisterNode { address } => self.do_register_node(address).await,
+            ChillieOperation::UpdateRoomMetadata { room_id, metadata } => {
+                self.do_update_room_metadata(room_id, metadata).await
+            }
+            ChillieOperation::AttachRecording { room_id, blob_id, duration_secs, size_bytes, replay_price } => {
+                self.do_attach_recording(room_id, blob_id, duration_secs, size_bytes, replay_price).await
+            }
+            ChillieOperation::JoinRoom { room_id, invite_code } => self.do_join_room(room_id, invite_code).await,
+            ChillieOperation::LeaveRoom { room_id } => self.do_leave_room(room_id).await,
+            ChillieOperation::KickParticipant { room_id, user_id } => self.do_kick_participant(room_id, user_id).await,
+            ChillieOperation::UpdateRoomAccess { room_id, allowlist, invite_code } => {
+                self.do_update_room_access(room_id, allowlist, invite_code).await
+            }
+            ChillieOperation::GrantRole { room_id, user_id, role } => self.do_grant_role(room_id, user_id, role).await,
+            ChillieOperation::AcceptCoHost { room_id } => self.do_accept_co_host(room_id).await,
+            ChillieOperation::RevokeRole { room_id, user_id } => self.do_revoke_role(room_id, user_id).await,
+            ChillieOperation::TransferHost { room_id, new_host } => self.do_transfer_host(room_id, new_host).await,
+            ChillieOperation::AcceptHost { room_id } => self.do_accept_host(room_id).await,
+            ChillieOperation::RegisterNode { address, region, bandwidth_mbps, commission_percent } => {
+                self.do_register_node(address, region, bandwidth_mbps, commission_percent).await
+            }
             ChillieOperation::UnregisterNode => self.do_unregister_node().await,
+            ChillieOperation::Delegate { node, amount } => self.do_delegate(node, amount).await,
+            ChillieOperation::Undelegate { node, amount } => self.do_undelegate(node, amount).await,
+            ChillieOperation::Heartbeat => self.do_heartbeat().await,
+            ChillieOperation::ReportNode { node, evidence } => self.do_report_node(node, evidence).await,
+            ChillieOperation::RebalanceRoom { room_id } => self.do_rebalance_room(room_id).await,
+            ChillieOperation::FundRewardPool { amount } => self.do_fund_reward_pool(amount).await,
+            ChillieOperation::DistributeRewards => self.do_distribute_rewards().await,
+            ChillieOperation::ClaimNodeRewards => self.do_claim_node_rewards().await,
+            ChillieOperation::UpdateTierConfig { config } => self.do_update_tier_config(config).await,
+            ChillieOperation::UpdateRewardConfig { config } => self.do_update_reward_config(config).await,
+            ChillieOperation::UpdateSlashingConfig { config } => self.do_update_slashing_config(config),
+            ChillieOperation::SetStakingToken { token } => self.do_set_staking_token(token).await,
+            ChillieOperation::SetRoomCreationPaused { paused } => self.do_set_room_creation_paused(paused),
+            ChillieOperation::ForceCloseRoom { room_id } => self.do_force_close_room(room_id).await,
+            ChillieOperation::WithdrawTreasury { token, amount, recipient } => {
+                self.do_withdraw_treasury(token, amount, recipient).await
+            }
+            ChillieOperation::FollowChain { chain_id } => self.do_follow_chain(chain_id),
+            ChillieOperation::UnfollowChain { chain_id } => self.do_unfollow_chain(chain_id),
+            ChillieOperation::IndexRooms { after, limit } => self.do_index_rooms(after, limit).await,
+        };
+
+        // Checks run before any state change, so a rejected operation only keeps the closing of
+        // rooms it found expired
+        result.unwrap_or_else(|error| ChillieResponse::Error { error })
+    }
+
+    async fn execute_message(&mut self, message: ChillieMessage) {
+        if self.runtime.message_is_bouncing() == Some(true) {
+            self.handle_bounced_message(message).await;
+            return;
+        }
+
+        match message {
+            ChillieMessage::InitRoomChain {
+                room_id,
+                host,
+                host_tier,
+                room_type,
+                required_stake,
+                relays,
+                starts_at,
+                ends_at,
+            } => {
+                self.init_room_chain(room_id, host, host_tier, room_type, required_stake, relays, starts_at, ends_at)
+                    .await
+            }
+            ChillieMessage::RoomUpdated { room_id, host, host_tier, roles, relays } => {
+                self.apply_room_update(room_id, host, host_tier, roles, relays).await
+            }
+            ChillieMessage::RoomSummary { room_id, participant_count, is_active } => {
+                self.apply_room_summary(room_id, participant_count, is_active).await
+            }
+            ChillieMessage::CloseRoom { room_id } => self.close_room_chain(room_id).await,
         }
     }
 
-    async fn execute_message(&mut self, _message: ()) {
-        panic!("Chillie application doesn't support any cross-chain messages");
+    async fn process_streams(&mut self, updates: Vec<StreamUpdate>) {
+        for update in updates {
+            for index in update.new_indices() {
+                let event = self.runtime.read_event(update.chain_id, update.stream_id.stream_name.clone(), index);
+                self.mirror_room_event(update.chain_id, event).await;
+            }
+        }
     }
 
     async fn store(mut self) {
@@ -68,24 +179,37 @@ impl Contract for ChillieContract {
 }
 
 impl ChillieContract {
-    async fn do_stake(&mut self, amount: Amount) -> ChillieResponse {
-        let user_id = self.runtime.authenticated_signer()
-            .expect("Authentication required")
-            .to_string();
+    /// Handles a tracked message that its recipient rejected
+    ///
+    /// Only `InitRoomChain` is tracked: a room whose chain failed to set up is closed again on the
+    /// main chain so that its relays and stake are released.
+    async fn handle_bounced_message(&mut self, message: ChillieMessage) {
+        let ChillieMessage::InitRoomChain { room_id, .. } = message else {
+            return;
+        };
+        let Some(room) = self.state.rooms.get(&room_id).await.expect("Failed to get room") else {
+            return;
+        };
+        if room.is_active {
+            self.deactivate_room(&room_id, room).await;
+        }
+    }
 
-        let token_type = self.state.staking_token.get();
-        
-        // In a real implementation, we would transfer tokens here based on token_type
-        match token_type {
-            SupportedToken::Native => {
-                // Native token transfer logic would go here
-                // For now, we assume the transfer is handled by the system or a separate mechanism
-            }
-            SupportedToken::Custom(_token_id) => {
-                // Custom token transfer logic (e.g., cross-application call)
-            }
+    async fn do_stake(&mut self, amount: Amount) -> Result<ChillieResponse, ChillieError> {
+        let owner = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?;
+        let user_id = owner.to_string();
+
+        // Stakes made before a token switch must be withdrawn before staking the new token
+        if self.state.holds_legacy_stake(&user_id).await.expect("Failed to get stake token") {
+            return Err(ChillieError::LegacyStakeToken);
         }
 
+        // Pull the stake into the application's account before crediting it
+        self.receive_tokens(owner, amount);
+        self.state.migrate_stake_token(&user_id).await
+            .expect("Failed to migrate stake token");
+
         let mut user_state = self.state.users.get(&user_id).await
             .expect("Failed to get user state")
             .unwrap_or(UserState {
@@ -96,52 +220,161 @@ impl ChillieContract {
         user_state.stake = user_state.stake.saturating_add(amount);
         self.state.users.insert(&user_id, user_state.clone())
             .expect("Failed to update user state");
+        self.emit(ChillieEvent::StakeChanged { user_id, stake: user_state.stake });
 
-        ChillieResponse::Staked { new_balance: user_state.stake }
+        Ok(ChillieResponse::Staked { new_balance: user_state.stake })
     }
 
-    async fn do_unstake(&mut self, amount: Amount) -> ChillieResponse {
+    async fn do_unstake(&mut self, amount: Amount) -> Result<ChillieResponse, ChillieError> {
         let user_id = self.runtime.authenticated_signer()
-            .expect("Authentication required")
+            .ok_or(ChillieError::Unauthenticated)?
             .to_string();
 
+        // Expired rooms no longer lock stake
+        self.close_expired_rooms(&user_id).await;
+
         let mut user_state = self.state.users.get(&user_id).await
             .expect("Failed to get user state")
-            .expect("User not found");
+            .ok_or(ChillieError::UserNotFound)?;
 
-        // Check if remaining stake is sufficient for active rooms
+        if amount > user_state.stake {
+            return Err(ChillieError::ExceedsStake);
+        }
         let new_balance = user_state.stake.saturating_sub(amount);
-        
-        for room_id in &user_state.active_rooms {
-            let room = self.state.rooms.get(room_id).await
-                .expect("Failed to get room")
-                .expect("Room not found");
-            
-            let required = room.room_type.required_tier().required_stake();
-            if new_balance < required {
-                panic!("Cannot unstake: Remaining balance insufficient for active room {}", room_id);
-            }
+
+        // Check if remaining stake is sufficient for active rooms and node registration
+        let now = self.runtime.system_time();
+        let locked = self.state.locked_stake(&user_id, &user_state, now).await
+            .expect("Failed to compute locked stake");
+        if new_balance < locked {
+            return Err(ChillieError::StakeLocked { locked });
         }
 
         user_state.stake = new_balance;
-        self.state.users.insert(&user_id, user_state.clone())
+        self.state.users.insert(&user_id, user_state)
             .expect("Failed to update user state");
 
-        // In a real implementation, we would transfer tokens back to the user here
+        // Drop the lock of a node that left the network once it has expired
+        if self.state.node_exits.get(&user_id).await
+            .expect("Failed to get node exit")
+            .is_some_and(|exit| exit.release_at <= now)
+        {
+            self.state.node_exits.remove(&user_id)
+                .expect("Failed to update node exit");
+            if !self.state.nodes.contains_key(&user_id).await.expect("Failed to get node") {
+                self.state.reports.remove(&user_id)
+                    .expect("Failed to clear node reports");
+            }
+        }
+
+        // Queue the withdrawal instead of paying out immediately
+        let release_at = now.saturating_add(TimeDelta::from_secs(UNBONDING_PERIOD_SECS));
+        let mut queue = self.state.unbonding.get(&user_id).await
+            .expect("Failed to get withdrawal queue")
+            .unwrap_or_default();
+        queue.push(PendingWithdrawal { amount, release_at, node: None });
+        self.state.unbonding.insert(&user_id, queue)
+            .expect("Failed to update withdrawal queue");
+
+        self.emit(ChillieEvent::StakeChanged { user_id, stake: new_balance });
 
-        ChillieResponse::Unstaked { new_balance }
+        Ok(ChillieResponse::Unstaked { new_balance, release_at })
     }
 
-    async fn do_create_room(&mut self, room_id: RoomId, room_type: RoomType) -> ChillieResponse {
-        let user_id = self.runtime.authenticated_signer()
-            .expect("Authentication required")
-            .to_string();
+    async fn do_claim_unstaked(&mut self) -> Result<ChillieResponse, ChillieError> {
+        let owner = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?;
+        let user_id = owner.to_string();
+
+        let queue = self.state.unbonding.get(&user_id).await
+            .expect("Failed to get withdrawal queue")
+            .unwrap_or_default();
+
+        let now = self.runtime.system_time();
+        let (matured, pending): (Vec<_>, Vec<_>) = queue.into_iter()
+            .partition(|entry| entry.release_at <= now);
+
+        if matured.is_empty() {
+            return Err(ChillieError::NothingToClaim);
+        }
+
+        let amount = matured.iter()
+            .fold(Amount::ZERO, |total, entry| total.saturating_add(entry.amount));
+        // Resolved while the queue still holds the claimed entries
+        let token = self.state.stake_token_of(&user_id).await
+            .expect("Failed to get stake token");
+
+        // Nodes can no longer slash undelegations that finished unbonding
+        for node in matured.iter().filter_map(|entry| entry.node.as_ref()) {
+            if pending.iter().any(|entry| entry.node.as_ref() == Some(node)) {
+                continue;
+            }
+            if let Some(mut delegators) = self.state.unbonding_delegators.get(node).await
+                .expect("Failed to get unbonding delegators")
+            {
+                delegators.remove(&user_id);
+                if delegators.is_empty() {
+                    self.state.unbonding_delegators.remove(node)
+                        .expect("Failed to update unbonding delegators");
+                } else {
+                    self.state.unbonding_delegators.insert(node, delegators)
+                        .expect("Failed to update unbonding delegators");
+                }
+            }
+        }
+
+        if pending.is_empty() {
+            self.state.unbonding.remove(&user_id)
+                .expect("Failed to update withdrawal queue");
+        } else {
+            self.state.unbonding.insert(&user_id, pending)
+                .expect("Failed to update withdrawal queue");
+        }
+
+        self.send_tokens(token, owner, amount);
+        self.release_legacy_token(&user_id).await;
+
+        Ok(ChillieResponse::UnstakeClaimed { amount })
+    }
+
+    async fn do_create_room(
+        &mut self,
+        room_id: RoomId,
+        room_type: RoomType,
+        dedicated_chain: bool,
+        starts_at: Option<Timestamp>,
+        ends_at: Option<Timestamp>,
+        metadata: RoomMetadata,
+    ) -> Result<ChillieResponse, ChillieError> {
+        let owner = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?;
+        let user_id = owner.to_string();
+
+        if *self.state.room_creation_paused.get() {
+            return Err(ChillieError::RoomCreationPaused);
+        }
+        if dedicated_chain && !room_type.supports_dedicated_chain() {
+            return Err(ChillieError::DedicatedChainNotSupported);
+        }
+        if let Some(ends_at) = ends_at {
+            if ends_at <= self.runtime.system_time() {
+                return Err(ChillieError::InvalidSchedule);
+            }
+            if starts_at.is_some_and(|starts_at| starts_at >= ends_at) {
+                return Err(ChillieError::InvalidSchedule);
+            }
+        }
+        let metadata = metadata.normalize()
+            .map_err(|reason| ChillieError::InvalidMetadata { reason: reason.to_string() })?;
 
         // Check if room already exists
         if self.state.rooms.get(&room_id).await.expect("Failed to check room").is_some() {
-            panic!("Room already exists");
+            return Err(ChillieError::RoomAlreadyExists);
         }
 
+        // Expired rooms no longer count as the host's active rooms
+        self.close_expired_rooms(&user_id).await;
+
         let mut user_state = self.state.users.get(&user_id).await
             .expect("Failed to get user state")
             .unwrap_or(UserState {
@@ -149,102 +382,2383 @@ impl ChillieContract {
                 active_rooms: Vec::new(),
             });
 
-        // Check stake requirement
-        let required_stake = room_type.required_tier().required_stake();
-        if user_state.stake < required_stake {
-            panic!("Insufficient stake for this room type. Required: {:?}", required_stake);
+        // Check stake requirement; stake held in a replaced token does not count
+        let stake = self.state.current_stake_of(&user_id).await
+            .expect("Failed to get user state");
+        let required_stake = self.state.tier_config.get().room_stake(room_type);
+        if stake < required_stake {
+            return Err(ChillieError::InsufficientStake { required: required_stake });
         }
 
         // Create room
-        let room_state = RoomState {
+        let host_tier = self.state.tier_config.get().tier_for_stake(stake);
+        let mut room_state = RoomState {
             host: user_id.clone(),
+            host_tier,
             room_type,
+            required_stake,
             participants: Vec::new(),
             is_active: true,
+            allowlist: None,
+            invite_code: None,
+            banned: Vec::new(),
+            roles: BTreeMap::new(),
+            co_host_invites: Vec::new(),
+            pending_host: None,
+            relays: Vec::new(),
+            closing_relays: Vec::new(),
+            chain_id: None,
+            participant_count: 0,
+            starts_at,
+            ends_at,
+            metadata,
         };
+        self.assign_relays(&room_id, &mut room_state).await;
+        self.index_tags(&room_id, &[], &room_state.metadata.tags);
+
+        if dedicated_chain {
+            let chain_id = self.open_room_chain(owner);
+            self.runtime
+                .prepare_message(ChillieMessage::InitRoomChain {
+                    room_id: room_id.clone(),
+                    host: user_id.clone(),
+                    host_tier,
+                    room_type,
+                    required_stake,
+                    relays: room_state.relays.clone(),
+                    starts_at,
+                    ends_at,
+                })
+                .with_tracking()
+                .send_to(chain_id);
+            room_state.chain_id = Some(chain_id);
+        } else {
+            self.notify_payment_processor(RoomLifecycle::Opened {
+                room_id: room_id.clone(),
+                host: user_id.clone(),
+                tier: host_tier,
+                relays: room_state.relays.clone(),
+            });
+        }
 
+        let chain_id = room_state.chain_id;
         self.state.rooms.insert(&room_id, room_state)
             .expect("Failed to create room");
+        self.state.index_host(&room_id, &user_id)
+            .expect("Failed to update host index");
 
         // Update user active rooms
         user_state.active_rooms.push(room_id.clone());
         self.state.users.insert(&user_id, user_state)
             .expect("Failed to update user state");
 
-        ChillieResponse::RoomCreated { room_id }
+        self.emit(ChillieEvent::RoomCreated { room_id: room_id.clone(), host: user_id, room_type });
+
+        Ok(ChillieResponse::RoomCreated { room_id, chain_id })
     }
 
-    async fn do_close_room(&mut self, room_id: RoomId) -> ChillieResponse {
+    async fn do_close_room(&mut self, room_id: RoomId) -> Result<ChillieResponse, ChillieError> {
         let user_id = self.runtime.authenticated_signer()
-            .expect("Authentication required")
+            .ok_or(ChillieError::Unauthenticated)?
             .to_string();
 
-        let mut room = self.state.rooms.get(&room_id).await
+        let room = self.state.rooms.get(&room_id).await
             .expect("Failed to get room")
-            .expect("Room not found");
+            .ok_or(ChillieError::RoomNotFound)?;
 
-        if room.host != user_id {
-            panic!("Only host can close the room");
+        if !room.can_manage(&user_id) {
+            return Err(ChillieError::NotRoomManager);
+        }
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+
+        self.close_room(&room_id, room).await;
+
+        Ok(ChillieResponse::RoomClosed { room_id })
+    }
+
+    /// Closes a room and tells the other side of a dedicated room about it
+    async fn close_room(&mut self, room_id: &RoomId, room: RoomState) {
+        let peer = match room.chain_id {
+            None => None,
+            Some(chain_id) if chain_id != self.runtime.chain_id() => {
+                Some((chain_id, ChillieMessage::CloseRoom { room_id: room_id.clone() }))
+            }
+            Some(_) => {
+                let summary = ChillieMessage::RoomSummary {
+                    room_id: room_id.clone(),
+                    participant_count: 0,
+                    is_active: false,
+                };
+                Some((self.runtime.application_creator_chain_id(), summary))
+            }
+        };
+
+        self.deactivate_room(room_id, room).await;
+
+        if let Some((chain_id, message)) = peer {
+            self.runtime.prepare_message(message).send_to(chain_id);
         }
+    }
 
+    /// Deactivates a room, frees its relays and releases the stake backing it
+    ///
+    /// Relays and stake are tracked on the main chain, so a room chain only clears its participants.
+    async fn deactivate_room(&mut self, room_id: &RoomId, mut room: RoomState) {
+        let current = self.runtime.chain_id();
+        let backers = room.backers();
+        let on_room_chain = room.is_room_chain(current);
+        let hosted_here = !room.is_hosted_elsewhere(current);
         room.is_active = false;
+        room.participants.clear();
+        room.participant_count = 0;
+        self.index_tags(room_id, &room.metadata.tags, &[]);
+        if !on_room_chain {
+            room.closing_relays = room.relays.clone();
+            self.release_relays(room_id, &mut room).await;
+        }
+        self.state.rooms.insert(room_id, room)
+            .expect("Failed to update room");
+
+        // Release the stake of everyone backing the room
+        if !on_room_chain {
+            for backer in backers {
+                self.remove_active_room(&backer, room_id).await;
+            }
+        }
+
+        if hosted_here {
+            self.notify_payment_processor(RoomLifecycle::Closed { room_id: room_id.clone() });
+        }
+        self.emit(ChillieEvent::RoomClosed { room_id: room_id.clone() });
+    }
+
+    async fn do_join_room(
+        &mut self,
+        room_id: RoomId,
+        invite_code: Option<String>,
+    ) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_hosted_here(&room)?;
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+        if !room.has_started(self.runtime.system_time()) {
+            return Err(ChillieError::RoomNotStarted);
+        }
+        if room.participants.contains(&user_id) {
+            return Err(ChillieError::AlreadyInRoom);
+        }
+        if !room.admits(&user_id, invite_code.as_deref()) {
+            return Err(ChillieError::NotAdmitted);
+        }
+        if room.participants.len() >= room.room_type.max_participants() {
+            return Err(ChillieError::RoomFull);
+        }
+
+        room.participants.push(user_id.clone());
+        let participant_count = room.participants.len();
+        self.report_summary(&room_id, &room);
         self.state.rooms.insert(&room_id, room)
             .expect("Failed to update room");
+        self.emit(ChillieEvent::ParticipantJoined { room_id: room_id.clone(), user_id });
 
-        // Remove from user active rooms
-        let mut user_state = self.state.users.get(&user_id).await
-            .expect("Failed to get user state")
-            .expect("User not found");
+        Ok(ChillieResponse::ParticipantJoined { room_id, participant_count })
+    }
 
-        user_state.active_rooms.retain(|id| id != &room_id);
-        self.state.users.insert(&user_id, user_state)
-            .expect("Failed to update user state");
+    async fn do_leave_room(&mut self, room_id: RoomId) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_hosted_here(&room)?;
+        if !room.participants.contains(&user_id) {
+            return Err(ChillieError::NotInRoom);
+        }
 
-        ChillieResponse::RoomClosed { room_id }
+        room.participants.retain(|id| id != &user_id);
+        self.report_summary(&room_id, &room);
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+        self.emit(ChillieEvent::ParticipantLeft { room_id: room_id.clone(), user_id });
+
+        Ok(ChillieResponse::ParticipantLeft { room_id })
     }
 
-    async fn do_register_node(&mut self, address: String) -> ChillieResponse {
+    async fn do_kick_participant(&mut self, room_id: RoomId, target: UserId) -> Result<ChillieResponse, ChillieError> {
         let user_id = self.runtime.authenticated_signer()
-            .expect("Authentication required")
+            .ok_or(ChillieError::Unauthenticated)?
             .to_string();
 
-        let user_state = self.state.users.get(&user_id).await
-            .expect("Failed to get user state")
-            .unwrap_or(UserState {
-                stake: Amount::ZERO,
-                active_rooms: Vec::new(),
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_hosted_here(&room)?;
+        if !room.can_moderate(&user_id) {
+            return Err(ChillieError::NotModerator);
+        }
+        if room.role_of(&target).is_some() {
+            return Err(ChillieError::ProtectedParticipant);
+        }
+
+        let was_participant = room.participants.contains(&target);
+        room.participants.retain(|id| id != &target);
+        if !room.banned.contains(&target) {
+            room.banned.push(target.clone());
+        }
+        if was_participant {
+            self.report_summary(&room_id, &room);
+        }
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+        if was_participant {
+            self.emit(ChillieEvent::ParticipantLeft { room_id: room_id.clone(), user_id: target.clone() });
+        }
+
+        Ok(ChillieResponse::ParticipantKicked { room_id, user_id: target })
+    }
+
+    async fn do_update_room_access(
+        &mut self,
+        room_id: RoomId,
+        allowlist: Option<Vec<UserId>>,
+        invite_code: Option<String>,
+    ) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_hosted_here(&room)?;
+        if !room.can_manage(&user_id) {
+            return Err(ChillieError::NotRoomManager);
+        }
+        if invite_code.as_ref().is_some_and(|code| code.is_empty()) {
+            return Err(ChillieError::InvalidInviteCode);
+        }
+
+        // Allowlisting a user again lifts an earlier kick
+        if let Some(allowlist) = &allowlist {
+            room.banned.retain(|id| !allowlist.contains(id));
+        }
+        room.allowlist = allowlist;
+        room.invite_code = invite_code;
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+
+        Ok(ChillieResponse::RoomAccessUpdated { room_id })
+    }
+
+    async fn do_update_room_metadata(
+        &mut self,
+        room_id: RoomId,
+        metadata: RoomMetadata,
+    ) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_main_chain(&room)?;
+        if !room.can_manage(&user_id) {
+            return Err(ChillieError::NotRoomManager);
+        }
+        // Closed rooms are no longer in the tag index
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+
+        let metadata = metadata.normalize()
+            .map_err(|reason| ChillieError::InvalidMetadata { reason: reason.to_string() })?;
+        self.index_tags(&room_id, &room.metadata.tags, &metadata.tags);
+        room.metadata = metadata;
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+
+        Ok(ChillieResponse::RoomMetadataUpdated { room_id })
+    }
+
+    async fn do_attach_recording(
+        &mut self,
+        room_id: RoomId,
+        blob_id: BlobId,
+        duration_secs: u64,
+        size_bytes: u64,
+        replay_price: Option<Amount>,
+    ) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        // Recordings usually arrive after the room ended, so an expired room is closed and the
+        // recording attached all the same
+        self.close_if_expired(&room_id, &room).await;
+
+        // Recordings live next to the room's payments, so replays can be charged there
+        self.ensure_hosted_here(&room)?;
+        let relay = room.relays.contains(&user_id) || room.closing_relays.contains(&user_id);
+        if room.host != user_id && !relay {
+            return Err(ChillieError::NotHostOrRelay);
+        }
+        if !is_valid_blob_id(&blob_id) {
+            return Err(ChillieError::InvalidBlobId);
+        }
+        if replay_price == Some(Amount::ZERO) {
+            return Err(ChillieError::InvalidReplayPrice);
+        }
+
+        let mut recordings = self.state.recordings.get(&room_id).await
+            .expect("Failed to get recordings")
+            .unwrap_or_default();
+        if recordings.iter().any(|recording| recording.blob_id == blob_id) {
+            return Err(ChillieError::RecordingAlreadyAttached);
+        }
+
+        recordings.push(Recording {
+            blob_id: blob_id.clone(),
+            duration_secs,
+            size_bytes,
+            attached_by: user_id,
+            attached_at: self.runtime.system_time(),
+            replay_price,
+        });
+        self.state.recordings.insert(&room_id, recordings)
+            .expect("Failed to update recordings");
+
+        if let Some(replay_price) = replay_price {
+            self.notify_payment_processor(RoomLifecycle::RecordingAttached {
+                room_id: room_id.clone(),
+                blob_id: blob_id.clone(),
+                replay_price,
             });
+        }
+
+        Ok(ChillieResponse::RecordingAttached { room_id, blob_id })
+    }
 
-        // Check stake requirement for RelayNode
-        let required_stake = chillie::StakingTier::RelayNode.required_stake();
-        if user_state.stake < required_stake {
-            panic!("Insufficient stake to register as a node. Required: {:?}", required_stake);
+    /// Moves a room from the index entries of its old tags to those of its new tags
+    fn index_tags(&mut self, room_id: &RoomId, old_tags: &[String], new_tags: &[String]) {
+        for tag in old_tags.iter().filter(|tag| !new_tags.contains(tag)) {
+            self.state.rooms_by_tag.remove(&(tag.clone(), room_id.clone()))
+                .expect("Failed to update tag index");
         }
 
-        let node_state = NodeState {
-            address: address.clone(),
-            last_heartbeat: self.runtime.system_time().micros() as u64,
-            reputation: 100, // Initial reputation
+        for tag in new_tags.iter().filter(|tag| !old_tags.contains(tag)) {
+            self.state.rooms_by_tag.insert(&(tag.clone(), room_id.clone()), ())
+                .expect("Failed to update tag index");
+        }
+    }
+
+    async fn do_grant_role(
+        &mut self,
+        room_id: RoomId,
+        target: UserId,
+        role: RoomRole,
+    ) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_main_chain(&room)?;
+        if room.host != user_id {
+            return Err(ChillieError::NotHost);
+        }
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+        if target == room.host {
+            return Err(ChillieError::AlreadyHost);
+        }
+        match role {
+            RoomRole::Host => return Err(ChillieError::InvalidRole),
+            RoomRole::CoHost if room.room_type != RoomType::MultiStream => {
+                return Err(ChillieError::InvalidRole);
+            }
+            _ => {}
+        }
+
+        // Co-hosts back the room with their own stake, so they must accept the role themselves
+        if role == RoomRole::CoHost && room.roles.get(&target) != Some(&RoomRole::CoHost) {
+            if !room.co_host_invites.contains(&target) {
+                room.co_host_invites.push(target.clone());
+            }
+            self.state.rooms.insert(&room_id, room)
+                .expect("Failed to update room");
+            return Ok(ChillieResponse::CoHostInvited { room_id, user_id: target });
+        }
+
+        room.co_host_invites.retain(|invited| *invited != target);
+        let previous = room.roles.insert(target.clone(), role);
+
+        // A demoted co-host stops backing the room, so the remaining hosts must cover its stake
+        if previous == Some(RoomRole::CoHost) && role != RoomRole::CoHost {
+            let backing = self.state.backing_stake(&room).await
+                .expect("Failed to compute room stake");
+            if backing < room.required_stake {
+                return Err(ChillieError::InsufficientStake { required: room.required_stake });
+            }
+        }
+
+        self.sync_room_chain(&room_id, &room);
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+
+        if previous == Some(RoomRole::CoHost) && role != RoomRole::CoHost {
+            self.remove_active_room(&target, &room_id).await;
+        }
+
+        Ok(ChillieResponse::RoleGranted { room_id, user_id: target, role })
+    }
+
+    async fn do_accept_co_host(&mut self, room_id: RoomId) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_main_chain(&room)?;
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+        let Some(index) = room.co_host_invites.iter().position(|invited| *invited == user_id) else {
+            return Err(ChillieError::NotInvited);
         };
 
-        self.state.nodes.insert(&user_id, node_state)
-            .expect("Failed to register node");
+        room.co_host_invites.remove(index);
+        room.roles.insert(user_id.clone(), RoomRole::CoHost);
+        self.sync_room_chain(&room_id, &room);
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+        self.add_active_room(&user_id, &room_id).await;
 
-        ChillieResponse::NodeRegistered { address }
+        Ok(ChillieResponse::RoleGranted { room_id, user_id, role: RoomRole::CoHost })
     }
 
-    async fn do_unregister_node(&mut self) -> ChillieResponse {
+    async fn do_revoke_role(&mut self, room_id: RoomId, target: UserId) -> Result<ChillieResponse, ChillieError> {
         let user_id = self.runtime.authenticated_signer()
-            .expect("Authentication required")
+            .ok_or(ChillieError::Unauthenticated)?
             .to_string();
 
-        if self.state.nodes.get(&user_id).await.expect("Failed to get node").is_none() {
-            panic!("Node not registered");
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_main_chain(&room)?;
+        // Anyone may give up their own role or decline a co-host invite
+        if room.host != user_id && target != user_id {
+            return Err(ChillieError::NotHost);
         }
 
-        self.state.nodes.remove(&user_id)
-            .expect("Failed to unregister node");
+        let invited = room.co_host_invites.len();
+        room.co_host_invites.retain(|invited| *invited != target);
+        let offered_host = room.pending_host.take_if(|pending| *pending == target).is_some();
+        if room.co_host_invites.len() < invited || offered_host {
+            self.state.rooms.insert(&room_id, room)
+                .expect("Failed to update room");
+            return Ok(ChillieResponse::RoleRevoked { room_id, user_id: target });
+        }
+
+        let previous = room.roles.remove(&target)
+            .ok_or(ChillieError::NoRevocableRole)?;
+
+        // Co-hosts may always step down; only the host is held to the room's stake requirement
+        if previous == RoomRole::CoHost && room.is_active && target != user_id {
+            let backing = self.state.backing_stake(&room).await
+                .expect("Failed to compute room stake");
+            if backing < room.required_stake {
+                return Err(ChillieError::InsufficientStake { required: room.required_stake });
+            }
+        }
+
+        self.sync_room_chain(&room_id, &room);
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
 
-        ChillieResponse::NodeUnregistered
+        if previous == RoomRole::CoHost {
+            self.remove_active_room(&target, &room_id).await;
+        }
+
+        Ok(ChillieResponse::RoleRevoked { room_id, user_id: target })
     }
-}
\ No newline at end of file
+
+    async fn do_transfer_host(&mut self, room_id: RoomId, new_host: UserId) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_main_chain(&room)?;
+        if room.host != user_id {
+            return Err(ChillieError::NotHost);
+        }
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+        if new_host == user_id {
+            return Err(ChillieError::AlreadyHost);
+        }
+
+        // The new host backs the room with their own stake, so they must accept the room themselves
+        room.pending_host = Some(new_host.clone());
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+
+        Ok(ChillieResponse::HostTransferProposed { room_id, new_host })
+    }
+
+    async fn do_accept_host(&mut self, room_id: RoomId) -> Result<ChillieResponse, ChillieError> {
+        let new_host = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_main_chain(&room)?;
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+        if room.pending_host.as_ref() != Some(&new_host) {
+            return Err(ChillieError::NoPendingHostTransfer);
+        }
+
+        let previous_host = std::mem::replace(&mut room.host, new_host.clone());
+        room.pending_host = None;
+        let was_co_host = room.roles.remove(&new_host) == Some(RoomRole::CoHost);
+        room.co_host_invites.retain(|invited| *invited != new_host);
+        let new_host_stake = self.state.current_stake_of(&new_host).await
+            .expect("Failed to get user state");
+        room.host_tier = self.state.tier_config.get().tier_for_stake(new_host_stake);
+
+        let backing = self.state.backing_stake(&room).await
+            .expect("Failed to compute room stake");
+        if backing < room.required_stake {
+            return Err(ChillieError::InsufficientStake { required: room.required_stake });
+        }
+
+        // A room chain reports the new host to its own payment processor
+        let hosted_here = room.chain_id.is_none();
+        let host_tier = room.host_tier;
+        self.sync_room_chain(&room_id, &room);
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+
+        self.remove_active_room(&previous_host, &room_id).await;
+        if !was_co_host {
+            self.add_active_room(&new_host, &room_id).await;
+        }
+        self.state.unindex_host(&room_id, &previous_host)
+            .expect("Failed to update host index");
+        self.state.index_host(&room_id, &new_host)
+            .expect("Failed to update host index");
+
+        if hosted_here {
+            self.notify_payment_processor(RoomLifecycle::HostChanged {
+                room_id: room_id.clone(),
+                host: new_host.clone(),
+                tier: host_tier,
+            });
+        }
+
+        Ok(ChillieResponse::HostTransferred { room_id, new_host })
+    }
+
+    async fn do_register_node(
+        &mut self,
+        address: String,
+        region: String,
+        bandwidth_mbps: u64,
+        commission_percent: u8,
+    ) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        if self.state.nodes.contains_key(&user_id).await.expect("Failed to get node") {
+            return Err(ChillieError::NodeAlreadyRegistered);
+        }
+
+        // Operators must back their node themselves; delegations cover the rest of the requirement
+        let tier_config = self.state.tier_config.get();
+        let required_stake = tier_config.relay_node;
+        let required_self_stake = tier_config.relay_node_self_stake;
+        let stake = self.state.current_stake_of(&user_id).await
+            .expect("Failed to get user state");
+        if stake < required_self_stake {
+            return Err(ChillieError::InsufficientStake { required: required_self_stake });
+        }
+
+        if region.is_empty() || region.len() > 64 {
+            return Err(ChillieError::InvalidRegion);
+        }
+        if commission_percent > 100 {
+            return Err(ChillieError::InvalidCommission);
+        }
+        if !is_valid_node_address(&address) {
+            return Err(ChillieError::InvalidNodeAddress);
+        }
+
+        let node_state = NodeState {
+            address: address.clone(),
+            region,
+            bandwidth_mbps,
+            required_stake,
+            required_self_stake,
+            last_heartbeat: self.runtime.system_time().micros() as u64,
+            reputation: MAX_REPUTATION,
+            missed_heartbeats: 0,
+            assigned_rooms: Vec::new(),
+            commission_percent,
+        };
+
+        self.state.nodes.insert(&user_id, node_state)
+            .expect("Failed to register node");
+        self.emit(ChillieEvent::NodeRegistered { node: user_id, address: address.clone() });
+
+        Ok(ChillieResponse::NodeRegistered { address })
+    }
+
+    async fn do_unregister_node(&mut self) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut node = self.state.nodes.get(&user_id).await
+            .expect("Failed to get node")
+            .ok_or(ChillieError::NodeNotRegistered)?;
+
+        // Leaving the network does not escape penalties the node has already earned
+        let config = *self.state.slashing_config.get();
+        let now = self.runtime.system_time();
+        self.apply_missed_heartbeats(&mut node, now.micros(), &config);
+        if let Some(amount) = self.slash_if_needed(&user_id, &node, &config).await {
+            return Ok(ChillieResponse::NodeSlashed { node: user_id, amount });
+        }
+
+        // The operator's share of the requirement stays locked, and slashable, through the
+        // unbonding period; pending reports carry over
+        let delegated = self.state.delegated_stake(&user_id).await
+            .expect("Failed to get delegations");
+        let exit = NodeExit {
+            locked: node.own_backing(delegated),
+            release_at: now.saturating_add(TimeDelta::from_secs(UNBONDING_PERIOD_SECS)),
+            reputation: node.reputation,
+        };
+        self.state.node_exits.insert(&user_id, exit)
+            .expect("Failed to record node exit");
+
+        self.state.nodes.remove(&user_id)
+            .expect("Failed to unregister node");
+        self.reassign_node_rooms(&user_id, &node).await;
+        self.emit(ChillieEvent::NodeUnregistered { node: user_id });
+
+        Ok(ChillieResponse::NodeUnregistered)
+    }
+
+    async fn do_delegate(&mut self, node: UserId, amount: Amount) -> Result<ChillieResponse, ChillieError> {
+        let owner = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?;
+        let user_id = owner.to_string();
+
+        if node == user_id {
+            return Err(ChillieError::SelfDelegation);
+        }
+        if amount == Amount::ZERO {
+            return Err(ChillieError::InvalidAmount);
+        }
+        if !self.state.nodes.contains_key(&node).await.expect("Failed to get node") {
+            return Err(ChillieError::NodeNotRegistered);
+        }
+        // Queued undelegations must not mix tokens
+        if self.state.holds_legacy_stake(&user_id).await.expect("Failed to get stake token") {
+            return Err(ChillieError::LegacyStakeToken);
+        }
+
+        self.receive_tokens(owner, amount);
+        self.state.migrate_stake_token(&user_id).await
+            .expect("Failed to migrate stake token");
+
+        let mut delegations = self.state.delegations.get(&node).await
+            .expect("Failed to get delegations")
+            .unwrap_or_default();
+        let delegated = delegations.get(&user_id).copied().unwrap_or_default().saturating_add(amount);
+        delegations.insert(user_id.clone(), delegated);
+        self.state.delegations.insert(&node, delegations)
+            .expect("Failed to update delegations");
+        self.emit(ChillieEvent::DelegationChanged { node: node.clone(), delegator: user_id, amount: delegated });
+
+        Ok(ChillieResponse::Delegated { node, delegated })
+    }
+
+    async fn do_undelegate(&mut self, node: UserId, amount: Amount) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut delegations = self.state.delegations.get(&node).await
+            .expect("Failed to get delegations")
+            .unwrap_or_default();
+        let current = delegations.get(&user_id).copied().unwrap_or_default();
+        if amount == Amount::ZERO || amount > current {
+            return Err(ChillieError::InvalidAmount);
+        }
+
+        let delegated = current.saturating_sub(amount);
+        if delegated == Amount::ZERO {
+            delegations.remove(&user_id);
+        } else {
+            delegations.insert(user_id.clone(), delegated);
+        }
+        if delegations.is_empty() {
+            self.state.delegations.remove(&node)
+                .expect("Failed to update delegations");
+        } else {
+            self.state.delegations.insert(&node, delegations)
+                .expect("Failed to update delegations");
+        }
+
+        // Undelegated stake unbonds like unstaked stake, and the node can still slash it meanwhile
+        let release_at = self.runtime.system_time()
+            .saturating_add(TimeDelta::from_secs(UNBONDING_PERIOD_SECS));
+        let mut queue = self.state.unbonding.get(&user_id).await
+            .expect("Failed to get withdrawal queue")
+            .unwrap_or_default();
+        queue.push(PendingWithdrawal { amount, release_at, node: Some(node.clone()) });
+        self.state.unbonding.insert(&user_id, queue)
+            .expect("Failed to update withdrawal queue");
+        let mut unbonding_delegators = self.state.unbonding_delegators.get(&node).await
+            .expect("Failed to get unbonding delegators")
+            .unwrap_or_default();
+        unbonding_delegators.insert(user_id.clone());
+        self.state.unbonding_delegators.insert(&node, unbonding_delegators)
+            .expect("Failed to update unbonding delegators");
+
+        self.emit(ChillieEvent::DelegationChanged { node: node.clone(), delegator: user_id, amount: delegated });
+
+        Ok(ChillieResponse::Undelegated { node, delegated, release_at })
+    }
+
+    async fn do_heartbeat(&mut self) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        let mut node = self.state.nodes.get(&user_id).await
+            .expect("Failed to get node")
+            .ok_or(ChillieError::NodeNotRegistered)?;
+
+        let config = *self.state.slashing_config.get();
+        let now = self.runtime.system_time().micros();
+
+        // Settle any missed intervals before crediting this heartbeat
+        self.apply_missed_heartbeats(&mut node, now, &config);
+        // A node that missed too many heartbeats is slashed instead of revived
+        if let Some(amount) = self.slash_if_needed(&user_id, &node, &config).await {
+            return Ok(ChillieResponse::NodeSlashed { node: user_id, amount });
+        }
+
+        node.reputation = node.reputation
+            .saturating_add(config.heartbeat_reward)
+            .min(MAX_REPUTATION);
+        node.last_heartbeat = now;
+        node.missed_heartbeats = 0;
+        let reputation = node.reputation;
+        self.state.nodes.insert(&user_id, node)
+            .expect("Failed to update node");
+
+        Ok(ChillieResponse::HeartbeatRecorded { reputation })
+    }
+
+    async fn do_report_node(&mut self, target: UserId, evidence: String) -> Result<ChillieResponse, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        if evidence.is_empty() || evidence.len() > 1000 {
+            return Err(ChillieError::InvalidEvidence);
+        }
+        if target == user_id {
+            return Err(ChillieError::SelfReport);
+        }
+        let by_admin = self.state.admin.get().as_ref() == Some(&user_id);
+        if !by_admin && !self.state.nodes.contains_key(&user_id).await.expect("Failed to get node") {
+            return Err(ChillieError::NotReporter);
+        }
+
+        let config = *self.state.slashing_config.get();
+        let now = self.runtime.system_time();
+        // Nodes that left the network can be slashed until their operator's stake is released
+        let mut node = self.state.nodes.get(&target).await
+            .expect("Failed to get node");
+        let exit = match node {
+            Some(_) => None,
+            None => self.state.node_exits.get(&target).await
+                .expect("Failed to get node exit")
+                .filter(|exit| exit.release_at > now),
+        };
+        let mut reputation = match (&mut node, &exit) {
+            (Some(node), _) => {
+                self.apply_missed_heartbeats(node, now.micros(), &config);
+                node.reputation
+            }
+            (None, Some(exit)) => exit.reputation,
+            (None, None) => return Err(ChillieError::NodeNotRegistered),
+        };
+
+        let mut reports = self.state.reports.get(&target).await
+            .expect("Failed to get node reports")
+            .unwrap_or_default();
+        if reports.iter().any(|report| report.reporter == user_id) {
+            return Err(ChillieError::AlreadyReported);
+        }
+        reports.push(NodeReport {
+            reporter: user_id,
+            evidence,
+            timestamp: now.micros(),
+        });
+
+        // Penalize once enough distinct nodes agree, then start a new round of reports
+        if by_admin || reports.len() >= config.report_quorum {
+            reputation = reputation.saturating_sub(config.report_penalty);
+            reports.clear();
+        }
+
+        let slashed = match (node, exit) {
+            (Some(mut node), _) => {
+                node.reputation = reputation;
+                let slashed = self.slash_if_needed(&target, &node, &config).await;
+                if slashed.is_none() {
+                    self.state.nodes.insert(&target, node)
+                        .expect("Failed to update node");
+                }
+                slashed
+            }
+            (None, Some(mut exit)) => {
+                exit.reputation = reputation;
+                let slashed = self.slash_exit_if_needed(&target, &exit, &config).await;
+                if slashed.is_none() {
+                    self.state.node_exits.insert(&target, exit)
+                        .expect("Failed to update node exit");
+                }
+                slashed
+            }
+            (None, None) => None,
+        };
+        if slashed.is_none() {
+            self.state.reports.insert(&target, reports)
+                .expect("Failed to update node reports");
+        }
+
+        Ok(ChillieResponse::NodeReported { node: target, reputation, slashed })
+    }
+
+    /// Lowers a node's reputation for every heartbeat interval it missed and that was not yet penalized
+    fn apply_missed_heartbeats(&self, node: &mut NodeState, now: u64, config: &SlashingConfig) {
+        let interval = config.heartbeat_interval_secs.saturating_mul(1_000_000).max(1);
+        // The interval in which the next heartbeat is due does not count as missed
+        let missed = now.saturating_sub(node.last_heartbeat) / interval;
+        let unpenalized = missed.saturating_sub(node.missed_heartbeats);
+
+        node.reputation = node.reputation
+            .saturating_sub(unpenalized.saturating_mul(config.missed_heartbeat_penalty));
+        node.missed_heartbeats = node.missed_heartbeats.max(missed);
+    }
+
+    /// Slashes and unregisters a node whose reputation fell below the threshold
+    ///
+    /// Returns the total slashed amount, which is moved to the treasury.
+    async fn slash_if_needed(&mut self, node_id: &UserId, node: &NodeState, config: &SlashingConfig) -> Option<Amount> {
+        if node.reputation >= config.slash_threshold {
+            return None;
+        }
+
+        let slashed = self.slash_node(node_id, config, |delegated| node.own_backing(delegated)).await;
+        self.state.nodes.remove(node_id)
+            .expect("Failed to unregister node");
+        self.reassign_node_rooms(node_id, node).await;
+        Some(slashed)
+    }
+
+    /// Slashes a node that left the network but whose operator stake is still locked
+    ///
+    /// The penalty is taken from the stake locked on exit, after which the lock is released.
+    async fn slash_exit_if_needed(&mut self, node_id: &UserId, exit: &NodeExit, config: &SlashingConfig) -> Option<Amount> {
+        if exit.reputation >= config.slash_threshold {
+            return None;
+        }
+
+        let slashed = self.slash_node(node_id, config, |_| exit.locked).await;
+        self.state.node_exits.remove(node_id)
+            .expect("Failed to update node exit");
+        Some(slashed)
+    }
+
+    /// Takes the configured share of the stake backing a node and clears its reports
+    ///
+    /// The operator and every delegator lose the configured share of the stake they back the
+    /// node with, including stake they withdrew that is still unbonding. `own_backing` gives the
+    /// operator's part from the stake delegated to the node. Returns the total slashed amount,
+    /// which is moved to the treasury.
+    async fn slash_node(
+        &mut self,
+        node_id: &UserId,
+        config: &SlashingConfig,
+        own_backing: impl FnOnce(Amount) -> Amount,
+    ) -> Amount {
+        // Holders who emptied their stake would otherwise resolve to the current token
+        let operator_token = self.state.stake_token_of(node_id).await
+            .expect("Failed to get stake token");
+
+        let mut delegations = self.state.delegations.get(node_id).await
+            .expect("Failed to get delegations")
+            .unwrap_or_default();
+        let delegated = delegations.values()
+            .fold(Amount::ZERO, |total, amount| total.saturating_add(*amount));
+
+        let mut slashed_delegations = Amount::ZERO;
+        for (delegator, amount) in delegations.iter_mut() {
+            let penalty = config.penalty(*amount);
+            *amount = amount.saturating_sub(penalty);
+            slashed_delegations = slashed_delegations.saturating_add(penalty);
+            self.emit(ChillieEvent::DelegationChanged {
+                node: node_id.clone(),
+                delegator: delegator.clone(),
+                amount: *amount,
+            });
+        }
+        delegations.retain(|_, amount| *amount > Amount::ZERO);
+        if delegations.is_empty() {
+            self.state.delegations.remove(node_id)
+                .expect("Failed to update delegations");
+        } else {
+            self.state.delegations.insert(node_id, delegations)
+                .expect("Failed to update delegations");
+        }
+        // Delegations are always held in the current staking token
+        let staking_token = *self.state.staking_token.get();
+        self.credit_treasury(staking_token, slashed_delegations).await;
+        let mut slashed = slashed_delegations;
+
+        let unbonding_delegators = self.state.unbonding_delegators.get(node_id).await
+            .expect("Failed to get unbonding delegators")
+            .unwrap_or_default();
+        for delegator in unbonding_delegators {
+            let token = self.state.stake_token_of(&delegator).await
+                .expect("Failed to get stake token");
+            let penalty = self.slash_unbonding(&delegator, Some(node_id), |amount| config.penalty(amount)).await;
+            self.credit_treasury(token, penalty).await;
+            slashed = slashed.saturating_add(penalty);
+        }
+        self.state.unbonding_delegators.remove(node_id)
+            .expect("Failed to update unbonding delegators");
+
+        // Stake the operator unstaked is taken once the remaining stake cannot cover the penalty
+        let mut penalty = config.penalty(own_backing(delegated));
+        let mut from_stake = Amount::ZERO;
+        if let Some(mut user_state) = self.state.users.get(node_id).await.expect("Failed to get user state") {
+            from_stake = penalty.min(user_state.stake);
+            user_state.stake = user_state.stake.saturating_sub(from_stake);
+            let stake = user_state.stake;
+            self.state.users.insert(node_id, user_state)
+                .expect("Failed to update user state");
+            penalty = penalty.saturating_sub(from_stake);
+            self.emit(ChillieEvent::StakeChanged { user_id: node_id.clone(), stake });
+        }
+        let from_unbonding = self.slash_unbonding(node_id, None, |amount| {
+            let taken = amount.min(penalty);
+            penalty = penalty.saturating_sub(taken);
+            taken
+        })
+        .await;
+        let own_slashed = from_stake.saturating_add(from_unbonding);
+        self.credit_treasury(operator_token, own_slashed).await;
+        slashed = slashed.saturating_add(own_slashed);
+
+        self.state.reports.remove(node_id)
+            .expect("Failed to clear node reports");
+        self.emit(ChillieEvent::NodeSlashed { node: node_id.clone(), amount: slashed });
+
+        slashed
+    }
+
+    /// Slashes the withdrawals of a user that are still unbonding and came from `source`
+    ///
+    /// `source` is the node the stake was undelegated from, or `None` for unstaked stake. Returns
+    /// the total `penalty` took from the matching entries.
+    async fn slash_unbonding(
+        &mut self,
+        user_id: &UserId,
+        source: Option<&UserId>,
+        mut penalty: impl FnMut(Amount) -> Amount,
+    ) -> Amount {
+        let now = self.runtime.system_time();
+        let mut queue = self.state.unbonding.get(user_id).await
+            .expect("Failed to get withdrawal queue")
+            .unwrap_or_default();
+
+        let mut slashed = Amount::ZERO;
+        for entry in queue.iter_mut().filter(|entry| entry.release_at > now && entry.node.as_ref() == source) {
+            let taken = penalty(entry.amount).min(entry.amount);
+            entry.amount = entry.amount.saturating_sub(taken);
+            slashed = slashed.saturating_add(taken);
+        }
+
+        queue.retain(|entry| entry.amount > Amount::ZERO);
+        if queue.is_empty() {
+            self.state.unbonding.remove(user_id)
+                .expect("Failed to update withdrawal queue");
+        } else {
+            self.state.unbonding.insert(user_id, queue)
+                .expect("Failed to update withdrawal queue");
+        }
+        slashed
+    }
+
+    async fn do_rebalance_room(&mut self, room_id: RoomId) -> Result<ChillieResponse, ChillieError> {
+        let mut room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+        if let Some(expired) = self.close_if_expired(&room_id, &room).await {
+            return Ok(expired);
+        }
+
+        self.ensure_main_chain(&room)?;
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+
+        let config = *self.state.slashing_config.get();
+        let now = self.runtime.system_time().micros();
+
+        // Drop relays that stopped sending heartbeats, lost reputation or lost their backing
+        for relay in room.relays.clone() {
+            let healthy = match self.state.nodes.get(&relay).await.expect("Failed to get node") {
+                Some(mut node) => {
+                    let backed = self.state.is_fully_backed(&relay, &node).await
+                        .expect("Failed to get node backing");
+                    let healthy = backed && node.is_healthy(now, &config);
+                    if !healthy {
+                        node.assigned_rooms.retain(|id| id != &room_id);
+                        self.state.nodes.insert(&relay, node)
+                            .expect("Failed to update node");
+                    }
+                    healthy
+                }
+                None => false,
+            };
+            if !healthy {
+                room.relays.retain(|id| id != &relay);
+            }
+        }
+
+        self.assign_relays(&room_id, &mut room).await;
+        let relays = room.relays.clone();
+        self.sync_room_chain(&room_id, &room);
+        if room.chain_id.is_none() {
+            self.notify_payment_processor(RoomLifecycle::RelaysChanged {
+                room_id: room_id.clone(),
+                relays: relays.clone(),
+            });
+        }
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+
+        Ok(ChillieResponse::RelaysAssigned { room_id, relays })
+    }
+
+    /// Fills a room's free relay slots with the healthy nodes that have the best reputation per load
+    async fn assign_relays(&mut self, room_id: &RoomId, room: &mut RoomState) {
+        let needed = room.room_type.relay_count().saturating_sub(room.relays.len());
+        if needed == 0 {
+            return;
+        }
+
+        let config = *self.state.slashing_config.get();
+        let now = self.runtime.system_time().micros();
+
+        let mut candidates = Vec::new();
+        for owner in self.state.nodes.indices().await.expect("Failed to list nodes") {
+            if room.relays.contains(&owner) {
+                continue;
+            }
+            if let Some(node) = self.state.nodes.get(&owner).await.expect("Failed to get node") {
+                let backed = self.state.is_fully_backed(&owner, &node).await
+                    .expect("Failed to get node backing");
+                if backed && node.is_healthy(now, &config) {
+                    candidates.push((owner, node));
+                }
+            }
+        }
+
+        // Stable sort keeps the assignment deterministic for equal scores
+        candidates.sort_by_key(|(_, node)| std::cmp::Reverse(node.assignment_score()));
+
+        for (owner, mut node) in candidates.into_iter().take(needed) {
+            node.assigned_rooms.push(room_id.clone());
+            self.state.nodes.insert(&owner, node)
+                .expect("Failed to update node");
+            room.relays.push(owner);
+        }
+    }
+
+    /// Releases every relay assigned to a room
+    async fn release_relays(&mut self, room_id: &RoomId, room: &mut RoomState) {
+        for relay in room.relays.drain(..) {
+            if let Some(mut node) = self.state.nodes.get(&relay).await.expect("Failed to get node") {
+                node.assigned_rooms.retain(|id| id != room_id);
+                self.state.nodes.insert(&relay, node)
+                    .expect("Failed to update node");
+            }
+        }
+    }
+
+    /// Moves the rooms of a node that left the network to other nodes
+    ///
+    /// Must be called after the node has been removed from `nodes`.
+    async fn reassign_node_rooms(&mut self, node_id: &UserId, node: &NodeState) {
+        for room_id in &node.assigned_rooms {
+            let Some(mut room) = self.state.rooms.get(room_id).await.expect("Failed to get room") else {
+                continue;
+            };
+            room.relays.retain(|id| id != node_id);
+            if room.is_active {
+                self.assign_relays(room_id, &mut room).await;
+                self.sync_room_chain(room_id, &room);
+                if room.chain_id.is_none() {
+                    self.notify_payment_processor(RoomLifecycle::RelaysChanged {
+                        room_id: room_id.clone(),
+                        relays: room.relays.clone(),
+                    });
+                }
+            }
+            self.state.rooms.insert(room_id, room)
+                .expect("Failed to update room");
+        }
+    }
+
+    async fn do_fund_reward_pool(&mut self, amount: Amount) -> Result<ChillieResponse, ChillieError> {
+        let owner = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?;
+
+        if amount == Amount::ZERO {
+            return Err(ChillieError::InvalidAmount);
+        }
+
+        self.receive_tokens(owner, amount);
+
+        let pool = self.state.reward_pool.get().saturating_add(amount);
+        self.state.reward_pool.set(pool);
+
+        Ok(ChillieResponse::RewardPoolFunded { pool })
+    }
+
+    async fn do_distribute_rewards(&mut self) -> Result<ChillieResponse, ChillieError> {
+        let reward_config = *self.state.reward_config.get();
+        let current_epoch = self.current_epoch(&reward_config);
+        let epochs = current_epoch.saturating_sub(*self.state.last_reward_epoch.get());
+        if epochs == 0 {
+            return Err(ChillieError::RewardsAlreadyDistributed);
+        }
+
+        let pool = *self.state.reward_pool.get();
+        let emission = Amount::from_attos(
+            u128::from(reward_config.emission_per_epoch).saturating_mul(u128::from(epochs)),
+        )
+        .min(pool);
+
+        let slashing_config = *self.state.slashing_config.get();
+        let now = self.runtime.system_time().micros();
+
+        // Nodes earn in proportion to reputation times the rooms they carry
+        let mut weights = Vec::new();
+        for owner in self.state.nodes.indices().await.expect("Failed to list nodes") {
+            if let Some(node) = self.state.nodes.get(&owner).await.expect("Failed to get node") {
+                let backed = self.state.is_fully_backed(&owner, &node).await
+                    .expect("Failed to get node backing");
+                if backed && node.is_healthy(now, &slashing_config) {
+                    let weight = u128::from(node.reputation) * (node.assigned_rooms.len() as u128 + 1);
+                    weights.push((owner, node.commission_percent, weight));
+                }
+            }
+        }
+
+        let total_weight: u128 = weights.iter().map(|(_, _, weight)| weight).sum();
+        let mut distributed = Amount::ZERO;
+        if total_weight > 0 {
+            for (owner, commission_percent, weight) in weights {
+                let share = Amount::from_attos(u128::from(emission) * weight / total_weight);
+                self.credit_node_rewards(&owner, commission_percent, share).await;
+                distributed = distributed.saturating_add(share);
+            }
+        }
+
+        // Rounding dust and emissions without healthy nodes stay in the pool
+        self.state.reward_pool.set(pool.saturating_sub(distributed));
+        self.state.last_reward_epoch.set(current_epoch);
+
+        Ok(ChillieResponse::RewardsDistributed { epochs, amount: distributed })
+    }
+
+    async fn do_claim_node_rewards(&mut self) -> Result<ChillieResponse, ChillieError> {
+        let owner = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?;
+        let user_id = owner.to_string();
+
+        let amount = self.state.rewards.get(&user_id).await
+            .expect("Failed to get rewards")
+            .unwrap_or_default();
+        if amount == Amount::ZERO {
+            return Err(ChillieError::NothingToClaim);
+        }
+
+        self.state.rewards.remove(&user_id)
+            .expect("Failed to update rewards");
+        let unclaimed = self.state.unclaimed_rewards.get().saturating_sub(amount);
+        self.state.unclaimed_rewards.set(unclaimed);
+        let token = *self.state.staking_token.get();
+        self.send_tokens(token, owner, amount);
+
+        Ok(ChillieResponse::NodeRewardsClaimed { amount })
+    }
+
+    async fn do_update_tier_config(&mut self, config: TierConfig) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+
+        if !config.is_valid() {
+            return Err(ChillieError::InvalidTierConfig);
+        }
+
+        self.state.tier_config.set(config);
+
+        Ok(ChillieResponse::TierConfigUpdated)
+    }
+
+    async fn do_update_reward_config(&mut self, config: RewardConfig) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+
+        if !config.is_valid() {
+            return Err(ChillieError::InvalidRewardConfig);
+        }
+
+        // Epochs elapsed under the old schedule are paid out before epoch numbers change
+        match self.do_distribute_rewards().await {
+            Ok(_) | Err(ChillieError::RewardsAlreadyDistributed) => {}
+            Err(error) => return Err(error),
+        }
+        let epoch = self.current_epoch(&config);
+        self.state.reward_config.set(config);
+        self.state.last_reward_epoch.set(epoch);
+
+        Ok(ChillieResponse::RewardConfigUpdated)
+    }
+
+    fn do_update_slashing_config(&mut self, config: SlashingConfig) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+
+        if !config.is_valid() {
+            return Err(ChillieError::InvalidSlashingConfig);
+        }
+
+        self.state.slashing_config.set(config);
+
+        Ok(ChillieResponse::SlashingConfigUpdated)
+    }
+
+    async fn do_set_staking_token(&mut self, token: SupportedToken) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+
+        let previous = *self.state.staking_token.get();
+        if token == previous {
+            return Err(ChillieError::SameStakingToken);
+        }
+
+        // Rewards are paid in the current staking token, so none may be outstanding
+        if *self.state.reward_pool.get() != Amount::ZERO || *self.state.unclaimed_rewards.get() != Amount::ZERO {
+            return Err(ChillieError::OutstandingRewards);
+        }
+        // Delegations are not pinned to a token, so they must be withdrawn first
+        let mut delegated = false;
+        self.state.delegations.for_each_index_while(|_| {
+            delegated = true;
+            Ok(false)
+        })
+        .await
+        .expect("Failed to list delegations");
+        if delegated {
+            return Err(ChillieError::OutstandingDelegations);
+        }
+
+        // Holders are pinned to the replaced token when they next change their stake
+        self.state.replaced_staking_tokens.push(previous);
+        self.state.staking_token.set(token);
+
+        Ok(ChillieResponse::StakingTokenChanged { token })
+    }
+
+    fn do_set_room_creation_paused(&mut self, paused: bool) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+
+        self.state.room_creation_paused.set(paused);
+
+        Ok(ChillieResponse::RoomCreationPaused { paused })
+    }
+
+    async fn do_force_close_room(&mut self, room_id: RoomId) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+
+        let room = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room")
+            .ok_or(ChillieError::RoomNotFound)?;
+
+        if !room.is_active {
+            return Err(ChillieError::RoomInactive);
+        }
+
+        self.close_room(&room_id, room).await;
+
+        Ok(ChillieResponse::RoomClosed { room_id })
+    }
+
+    async fn do_withdraw_treasury(
+        &mut self,
+        token: SupportedToken,
+        amount: Amount,
+        recipient: Account,
+    ) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+
+        let balance = self.state.treasury.get(&token).await
+            .expect("Failed to get treasury")
+            .unwrap_or_default();
+        if amount == Amount::ZERO || amount > balance {
+            return Err(ChillieError::InvalidAmount);
+        }
+
+        self.state.treasury.insert(&token, balance.saturating_sub(amount))
+            .expect("Failed to update treasury");
+        self.pay_out(token, recipient, amount);
+
+        Ok(ChillieResponse::TreasuryWithdrawn { amount })
+    }
+
+    /// Adds slashed stake to the treasury balance of its token
+    async fn credit_treasury(&mut self, token: SupportedToken, amount: Amount) {
+        let balance = self.state.treasury.get(&token).await
+            .expect("Failed to get treasury")
+            .unwrap_or_default();
+        self.state.treasury.insert(&token, balance.saturating_add(amount))
+            .expect("Failed to update treasury");
+    }
+
+    /// Forgets a user's legacy stake token once nothing is held in it anymore
+    async fn release_legacy_token(&mut self, user_id: &UserId) {
+        let stake = self.state.stake_of(user_id).await
+            .expect("Failed to get user state");
+        let unbonding = self.state.unbonding_stake(user_id).await
+            .expect("Failed to get withdrawal queue");
+        if stake != Amount::ZERO || unbonding != Amount::ZERO {
+            return;
+        }
+
+        self.state.legacy_stake_tokens.remove(user_id)
+            .expect("Failed to update stake token");
+    }
+
+    /// Fails unless the signer is the application admin
+    fn ensure_admin(&mut self) -> Result<UserId, ChillieError> {
+        let user_id = self.runtime.authenticated_signer()
+            .ok_or(ChillieError::Unauthenticated)?
+            .to_string();
+
+        if self.state.admin.get().as_ref() != Some(&user_id) {
+            return Err(ChillieError::NotAdmin);
+        }
+
+        Ok(user_id)
+    }
+
+    /// Adds to an owner's claimable reward balance
+    async fn credit_rewards(&mut self, owner: &UserId, amount: Amount) {
+        if amount == Amount::ZERO {
+            return;
+        }
+        let balance = self.state.rewards.get(owner).await
+            .expect("Failed to get rewards")
+            .unwrap_or_default();
+        self.state.rewards.insert(owner, balance.saturating_add(amount))
+            .expect("Failed to update rewards");
+        let unclaimed = self.state.unclaimed_rewards.get().saturating_add(amount);
+        self.state.unclaimed_rewards.set(unclaimed);
+    }
+
+    /// Splits a node's reward share between its operator and delegators
+    ///
+    /// The operator keeps its commission; the rest is shared in proportion to the operator's own
+    /// stake and each delegation, with rounding dust going to the operator.
+    async fn credit_node_rewards(&mut self, node_id: &UserId, commission_percent: u8, share: Amount) {
+        let delegations = self.state.delegations.get(node_id).await
+            .expect("Failed to get delegations")
+            .unwrap_or_default();
+        let own_stake = self.state.stake_of(node_id).await
+            .expect("Failed to get user state");
+
+        let total_stake = delegations.values()
+            .fold(u128::from(own_stake), |total, amount| total.saturating_add(u128::from(*amount)));
+        let commission = u128::from(share) * u128::from(commission_percent.min(100)) / 100;
+        let shared = u128::from(share) - commission;
+
+        let mut paid = Amount::ZERO;
+        if total_stake > 0 {
+            for (delegator, amount) in delegations {
+                let reward = Amount::from_attos(shared * u128::from(amount) / total_stake);
+                self.credit_rewards(&delegator, reward).await;
+                paid = paid.saturating_add(reward);
+            }
+        }
+
+        self.credit_rewards(node_id, share.saturating_sub(paid)).await;
+    }
+
+    /// Returns the index of the reward epoch containing the current block
+    fn current_epoch(&mut self, config: &RewardConfig) -> u64 {
+        let epoch_micros = config.epoch_duration_secs.saturating_mul(1_000_000).max(1);
+        self.runtime.system_time().micros() / epoch_micros
+    }
+
+    /// Records that a user's stake backs a room
+    async fn add_active_room(&mut self, user_id: &UserId, room_id: &RoomId) {
+        let mut user_state = self.state.users.get(user_id).await
+            .expect("Failed to get user state")
+            .unwrap_or(UserState {
+                stake: Amount::ZERO,
+                active_rooms: Vec::new(),
+            });
+
+        if !user_state.active_rooms.contains(room_id) {
+            user_state.active_rooms.push(room_id.clone());
+        }
+        self.state.users.insert(user_id, user_state)
+            .expect("Failed to update user state");
+    }
+
+    /// Releases a user's stake from a room
+    async fn remove_active_room(&mut self, user_id: &UserId, room_id: &RoomId) {
+        if let Some(mut user_state) = self.state.users.get(user_id).await
+            .expect("Failed to get user state")
+        {
+            user_state.active_rooms.retain(|id| id != room_id);
+            self.state.users.insert(user_id, user_state)
+                .expect("Failed to update user state");
+        }
+    }
+
+    fn do_follow_chain(&mut self, chain_id: ChainId) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+        if chain_id == self.runtime.chain_id() {
+            return Err(ChillieError::CannotFollowSelf);
+        }
+
+        let application_id = self.runtime.application_id().forget_abi();
+        self.runtime.subscribe_to_events(chain_id, application_id, StreamName::from(ROOM_EVENTS_STREAM));
+
+        Ok(ChillieResponse::ChainFollowed { chain_id })
+    }
+
+    fn do_unfollow_chain(&mut self, chain_id: ChainId) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+        let application_id = self.runtime.application_id().forget_abi();
+        self.runtime.unsubscribe_from_events(chain_id, application_id, StreamName::from(ROOM_EVENTS_STREAM));
+
+        Ok(ChillieResponse::ChainUnfollowed { chain_id })
+    }
+
+    /// Adds up to `limit` rooms after `after` to the host and tag indexes, for rooms created before
+    /// they existed
+    async fn do_index_rooms(&mut self, after: Option<RoomId>, limit: u32) -> Result<ChillieResponse, ChillieError> {
+        self.ensure_admin()?;
+
+        let limit = (limit as usize).clamp(1, MAX_PAGE_SIZE);
+        let room_ids = indices_after(&self.state.rooms, &[], after.as_ref(), limit + 1).await
+            .expect("Failed to list rooms");
+
+        for room_id in room_ids.iter().take(limit) {
+            if let Some(room) = self.state.rooms.get(room_id).await.expect("Failed to get room") {
+                self.state.index_host(room_id, &room.host)
+                    .expect("Failed to update host index");
+                if room.is_active {
+                    self.index_tags(room_id, &[], &room.metadata.tags);
+                }
+            }
+        }
+        let next_cursor = (room_ids.len() > limit).then(|| room_ids[limit - 1].clone());
+
+        Ok(ChillieResponse::RoomsIndexed { next_cursor })
+    }
+
+    /// Applies a room event read from a followed chain to the local mirror
+    async fn mirror_room_event(&mut self, chain_id: ChainId, event: ChillieEvent) {
+        if let ChillieEvent::RoomCreated { room_id, host, room_type } = event {
+            let room = FollowedRoomView {
+                chain_id,
+                room_id: room_id.clone(),
+                host,
+                room_type,
+                participant_count: 0,
+                is_active: true,
+            };
+            self.state.followed_rooms.insert(&(chain_id, room_id), room)
+                .expect("Failed to update followed room");
+            return;
+        }
+
+        let room_id = match &event {
+            ChillieEvent::RoomClosed { room_id }
+            | ChillieEvent::ParticipantJoined { room_id, .. }
+            | ChillieEvent::ParticipantLeft { room_id, .. } => room_id.clone(),
+            _ => return,
+        };
+        let key = (chain_id, room_id);
+        let Some(mut room) = self.state.followed_rooms.get(&key).await
+            .expect("Failed to get followed room")
+        else {
+            return;
+        };
+
+        match event {
+            ChillieEvent::RoomClosed { .. } => {
+                room.is_active = false;
+                room.participant_count = 0;
+            }
+            ChillieEvent::ParticipantJoined { .. } => room.participant_count += 1,
+            ChillieEvent::ParticipantLeft { .. } => {
+                room.participant_count = room.participant_count.saturating_sub(1);
+            }
+            _ => {}
+        }
+        self.state.followed_rooms.insert(&key, room)
+            .expect("Failed to update followed room");
+    }
+
+    /// Closes a room whose end time has passed
+    ///
+    /// Returns the response to give instead of running the operation, so that the closure is
+    /// committed rather than reverted by a panic.
+    async fn close_if_expired(&mut self, room_id: &RoomId, room: &RoomState) -> Option<ChillieResponse> {
+        if !room.is_active || !room.has_expired(self.runtime.system_time()) {
+            return None;
+        }
+
+        self.close_room(room_id, room.clone()).await;
+        Some(ChillieResponse::RoomExpired { room_id: room_id.clone() })
+    }
+
+    /// Closes the expired rooms a user backs
+    async fn close_expired_rooms(&mut self, user_id: &UserId) {
+        let Some(user_state) = self.state.users.get(user_id).await.expect("Failed to get user state") else {
+            return;
+        };
+
+        for room_id in user_state.active_rooms {
+            if let Some(room) = self.state.rooms.get(&room_id).await.expect("Failed to get room") {
+                self.close_if_expired(&room_id, &room).await;
+            }
+        }
+    }
+
+    /// Opens a dedicated chain for a room, owned by its host and open to anyone in multi-leader rounds
+    ///
+    /// The host funds the new chain's balance, which pays for the blocks proposed on it.
+    fn open_room_chain(&mut self, host: AccountOwner) -> ChainId {
+        let balance = self.runtime.application_parameters().room_chain_balance;
+        let chain_account = Account {
+            chain_id: self.runtime.chain_id(),
+            owner: AccountOwner::CHAIN,
+        };
+        self.runtime.transfer(host, chain_account, balance);
+
+        let mut ownership = ChainOwnership::multiple([(host, 100)], 10, TimeoutConfig::default());
+        // Participants propose their own joins and tips on the room chain
+        ownership.open_multi_leader_rounds = true;
+        self.runtime.open_chain(ownership, ApplicationPermissions::default(), balance)
+    }
+
+    /// Sets up the local copy of a room on its dedicated chain
+    async fn init_room_chain(
+        &mut self,
+        room_id: RoomId,
+        host: UserId,
+        host_tier: StakingTier,
+        room_type: RoomType,
+        required_stake: Amount,
+        relays: Vec<UserId>,
+        starts_at: Option<Timestamp>,
+        ends_at: Option<Timestamp>,
+    ) {
+        self.ensure_from_main_chain();
+        if self.state.rooms.contains_key(&room_id).await.expect("Failed to check room") {
+            panic!("Room already exists");
+        }
+
+        let room = RoomState {
+            host: host.clone(),
+            host_tier,
+            room_type,
+            required_stake,
+            participants: Vec::new(),
+            is_active: true,
+            allowlist: None,
+            invite_code: None,
+            banned: Vec::new(),
+            roles: BTreeMap::new(),
+            co_host_invites: Vec::new(),
+            pending_host: None,
+            relays: relays.clone(),
+            closing_relays: Vec::new(),
+            chain_id: Some(self.runtime.chain_id()),
+            participant_count: 0,
+            starts_at,
+            ends_at,
+            metadata: RoomMetadata::default(),
+        };
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to create room");
+
+        self.notify_payment_processor(RoomLifecycle::Opened {
+            room_id: room_id.clone(),
+            host: host.clone(),
+            tier: host_tier,
+            relays,
+        });
+        self.emit(ChillieEvent::RoomCreated { room_id, host, room_type });
+    }
+
+    /// Applies host, role and relay changes made on the main chain to a room chain
+    async fn apply_room_update(
+        &mut self,
+        room_id: RoomId,
+        host: UserId,
+        host_tier: StakingTier,
+        roles: BTreeMap<UserId, RoomRole>,
+        relays: Vec<UserId>,
+    ) {
+        self.ensure_from_main_chain();
+        let Some(mut room) = self.state.rooms.get(&room_id).await.expect("Failed to get room") else {
+            return;
+        };
+        if !room.is_active {
+            return;
+        }
+
+        let host_changed = room.host != host || room.host_tier != host_tier;
+        let relays_changed = room.relays != relays;
+        room.host = host.clone();
+        room.host_tier = host_tier;
+        room.roles = roles;
+        room.relays = relays.clone();
+        self.state.rooms.insert(&room_id, room)
+            .expect("Failed to update room");
+
+        if host_changed {
+            self.notify_payment_processor(RoomLifecycle::HostChanged {
+                room_id: room_id.clone(),
+                host,
+                tier: host_tier,
+            });
+        }
+        if relays_changed {
+            self.notify_payment_processor(RoomLifecycle::RelaysChanged { room_id, relays });
+        }
+    }
+
+    /// Records a room chain's summary on the main chain, closing the room if it was closed there
+    async fn apply_room_summary(&mut self, room_id: RoomId, participant_count: usize, is_active: bool) {
+        let Some(mut room) = self.state.rooms.get(&room_id).await.expect("Failed to get room") else {
+            return;
+        };
+        if room.chain_id.is_none() || self.runtime.message_origin_chain_id() != room.chain_id {
+            panic!("Room summaries must come from the room's own chain");
+        }
+        if !room.is_active {
+            return;
+        }
+
+        if is_active {
+            room.participant_count = participant_count;
+            self.state.rooms.insert(&room_id, room)
+                .expect("Failed to update room");
+        } else {
+            self.deactivate_room(&room_id, room).await;
+        }
+    }
+
+    /// Closes a room on its dedicated chain after the main chain closed it
+    async fn close_room_chain(&mut self, room_id: RoomId) {
+        self.ensure_from_main_chain();
+        let Some(room) = self.state.rooms.get(&room_id).await.expect("Failed to get room") else {
+            return;
+        };
+        if room.is_active {
+            self.deactivate_room(&room_id, room).await;
+        }
+    }
+
+    /// Sends the room's participant count to the main chain when running on the room's own chain
+    fn report_summary(&mut self, room_id: &RoomId, room: &RoomState) {
+        if !room.is_room_chain(self.runtime.chain_id()) {
+            return;
+        }
+
+        let summary = ChillieMessage::RoomSummary {
+            room_id: room_id.clone(),
+            participant_count: room.participants.len(),
+            is_active: room.is_active,
+        };
+        let main_chain = self.runtime.application_creator_chain_id();
+        self.runtime.prepare_message(summary).send_to(main_chain);
+    }
+
+    /// Pushes the room's host, roles and relays to its dedicated chain, if it has one
+    fn sync_room_chain(&mut self, room_id: &RoomId, room: &RoomState) {
+        let Some(chain_id) = room.chain_id.filter(|_| room.is_hosted_elsewhere(self.runtime.chain_id())) else {
+            return;
+        };
+
+        let update = ChillieMessage::RoomUpdated {
+            room_id: room_id.clone(),
+            host: room.host.clone(),
+            host_tier: room.host_tier,
+            roles: room.roles.clone(),
+            relays: room.relays.clone(),
+        };
+        self.runtime.prepare_message(update).send_to(chain_id);
+    }
+
+    /// Fails if participants of the room are handled by another chain
+    fn ensure_hosted_here(&mut self, room: &RoomState) -> Result<(), ChillieError> {
+        if let Some(chain_id) = room.chain_id.filter(|_| room.is_hosted_elsewhere(self.runtime.chain_id())) {
+            return Err(ChillieError::RoomHostedElsewhere { chain_id });
+        }
+        Ok(())
+    }
+
+    /// Fails when called on a room chain, since roles, relays and stake live on the main chain
+    fn ensure_main_chain(&mut self, room: &RoomState) -> Result<(), ChillieError> {
+        if room.is_room_chain(self.runtime.chain_id()) {
+            return Err(ChillieError::MainChainOnly);
+        }
+        Ok(())
+    }
+
+    /// Panics unless the message being executed was sent by the main chain
+    fn ensure_from_main_chain(&mut self) {
+        let main_chain = self.runtime.application_creator_chain_id();
+        if self.runtime.message_origin_chain_id() != Some(main_chain) {
+            panic!("Room chain messages must come from the main chain");
+        }
+    }
+
+    /// Publishes an event on the stream it belongs to
+    fn emit(&mut self, event: ChillieEvent) {
+        self.runtime.emit(event.stream_name(), &event);
+    }
+
+    /// Reports a room lifecycle change to the configured payment processor, if any
+    ///
+    /// Room changes do not depend on the payment processor accepting them, so a rejection is
+    /// published as an event instead of failing the block.
+    fn notify_payment_processor(&mut self, event: RoomLifecycle) {
+        let Some(payment_processor) = self.runtime.application_parameters().payment_processor else {
+            return;
+        };
+
+        let room_id = event.room_id().clone();
+        let operation = PaymentOperation::ApplyRoomLifecycle { event };
+        let result = self.runtime
+            .call_application(true, payment_processor.with_abi::<PaymentProcessorAbi>(), &operation);
+        if let PaymentResult::PaymentError { error } = result {
+            self.emit(ChillieEvent::PaymentSyncFailed { room_id, error: error.to_string() });
+        }
+    }
+
+    /// Returns the account that escrows staked tokens on this chain.
+    fn escrow_account(&mut self) -> Account {
+        Account {
+            chain_id: self.runtime.chain_id(),
+            owner: AccountOwner::from(self.runtime.application_id().forget_abi()),
+        }
+    }
+
+    /// Moves `amount` of the staking token from `owner` into the escrow account.
+    fn receive_tokens(&mut self, owner: AccountOwner, amount: Amount) {
+        let escrow = self.escrow_account();
+        match *self.state.staking_token.get() {
+            SupportedToken::Native => {
+                self.runtime.transfer(owner, escrow, amount);
+            }
+            SupportedToken::Custom(token_id) => {
+                let transfer = FungibleOperation::Transfer {
+                    owner,
+                    amount,
+                    target_account: escrow,
+                };
+                self.runtime
+                    .call_application(true, token_id.with_abi::<FungibleTokenAbi>(), &transfer);
+            }
+        }
+    }
+
+    /// Returns `amount` of `token` from the escrow account to `owner` on this chain.
+    fn send_tokens(&mut self, token: SupportedToken, owner: AccountOwner, amount: Amount) {
+        let destination = Account {
+            chain_id: self.runtime.chain_id(),
+            owner,
+        };
+        self.pay_out(token, destination, amount);
+    }
+
+    /// Moves `amount` of `token` from the escrow account to `destination`.
+    fn pay_out(&mut self, token: SupportedToken, destination: Account, amount: Amount) {
+        let escrow = self.escrow_account();
+        match token {
+            SupportedToken::Native => {
+                self.runtime.transfer(escrow.owner, destination, amount);
+            }
+            SupportedToken::Custom(token_id) => {
+                let transfer = FungibleOperation::Transfer {
+                    owner: escrow.owner,
+                    amount,
+                    target_account: destination,
+                };
+                self.runtime
+                    .call_application(true, token_id.with_abi::<FungibleTokenAbi>(), &transfer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linera_sdk::{
+        linera_base_types::{ApplicationId, CryptoHash},
+        util::BlockingWait,
+    };
+
+    fn owner(name: &str) -> AccountOwner {
+        AccountOwner::from(CryptoHash::test_hash(name))
+    }
+
+    fn user(name: &str) -> UserId {
+        owner(name).to_string()
+    }
+
+    fn create_and_instantiate() -> ChillieContract {
+        let application_id = ApplicationId::new(CryptoHash::test_hash("chillie")).with_abi::<ChillieAbi>();
+        let main_chain = ChainId(CryptoHash::test_hash("main"));
+        let balances = ["admin", "alice", "bob", "carol"]
+            .map(|name| (owner(name), Amount::from_tokens(100_000)));
+        let runtime = ContractRuntime::new()
+            .with_application_parameters(ChillieParameters::default())
+            .with_application_id(application_id)
+            .with_application_creator_chain_id(main_chain)
+            .with_chain_id(main_chain)
+            .with_authenticated_signer(owner("admin"))
+            .with_system_time(Timestamp::from(0))
+            .with_owner_balances(balances);
+        let mut contract = ChillieContract {
+            state: ChillieRoomState::load(runtime.root_view_storage_context())
+                .blocking_wait()
+                .expect("Failed to read from mock key value store"),
+            runtime,
+        };
+        contract.instantiate(InstantiationArgument::default()).blocking_wait();
+        contract
+    }
+
+    fn execute(contract: &mut ChillieContract, signer: &str, operation: ChillieOperation) -> ChillieResponse {
+        contract.runtime.set_authenticated_signer(owner(signer));
+        contract.execute_operation(operation).blocking_wait()
+    }
+
+    fn stake(contract: &mut ChillieContract, signer: &str, amount: Amount) {
+        let response = execute(contract, signer, ChillieOperation::Stake { amount });
+        assert!(matches!(response, ChillieResponse::Staked { .. }), "Stake failed: {:?}", response);
+    }
+
+    fn create_room(contract: &mut ChillieContract, host: &str, room_id: &str, room_type: RoomType) {
+        let response = execute(contract, host, ChillieOperation::CreateRoom {
+            room_id: room_id.to_string(),
+            room_type,
+            dedicated_chain: false,
+            starts_at: None,
+            ends_at: None,
+            metadata: RoomMetadata::default(),
+        });
+        assert!(matches!(response, ChillieResponse::RoomCreated { .. }), "Room creation failed: {:?}", response);
+    }
+
+    fn room(contract: &ChillieContract, room_id: &str) -> RoomState {
+        contract.state.rooms.get(&room_id.to_string())
+            .blocking_wait()
+            .expect("Failed to get room")
+            .expect("Room should exist")
+    }
+
+    fn active_rooms(contract: &ChillieContract, name: &str) -> Vec<RoomId> {
+        contract.state.users.get(&user(name))
+            .blocking_wait()
+            .expect("Failed to get user state")
+            .map(|user_state| user_state.active_rooms)
+            .unwrap_or_default()
+    }
+
+    fn advance(contract: &mut ChillieContract, secs: u64) {
+        let now = contract.runtime.system_time();
+        contract.runtime.set_system_time(now.saturating_add(TimeDelta::from_secs(secs)));
+    }
+
+    fn register_node(contract: &mut ChillieContract, operator: &str) {
+        stake(contract, operator, TierConfig::default().relay_node);
+        let response = execute(contract, operator, ChillieOperation::RegisterNode {
+            address: "127.0.0.1:8080".to_string(),
+            region: "global".to_string(),
+            bandwidth_mbps: 1000,
+            commission_percent: 10,
+        });
+        assert!(matches!(response, ChillieResponse::NodeRegistered { .. }), "Registration failed: {:?}", response);
+    }
+
+    fn stake_of(contract: &ChillieContract, name: &str) -> Amount {
+        contract.state.users.get(&user(name))
+            .blocking_wait()
+            .expect("Failed to get user state")
+            .map(|user_state| user_state.stake)
+            .unwrap_or_default()
+    }
+
+    fn assert_rejected(response: ChillieResponse, expected: ChillieError) {
+        match response {
+            ChillieResponse::Error { error } => assert_eq!(error, expected),
+            response => panic!("Expected {:?}, got {:?}", expected, response),
+        }
+    }
+
+    #[test]
+    fn test_host_transfer_waits_for_the_new_host() {
+        let mut contract = create_and_instantiate();
+        let required = TierConfig::default().multi_stream;
+        stake(&mut contract, "alice", required);
+        create_room(&mut contract, "alice", "room", RoomType::MultiStream);
+
+        let response = execute(&mut contract, "alice", ChillieOperation::TransferHost {
+            room_id: "room".to_string(),
+            new_host: user("bob"),
+        });
+        assert!(matches!(response, ChillieResponse::HostTransferProposed { .. }));
+        assert_eq!(room(&contract, "room").host, user("alice"));
+        assert!(active_rooms(&contract, "bob").is_empty());
+
+        let response = execute(&mut contract, "carol", ChillieOperation::AcceptHost { room_id: "room".to_string() });
+        assert_rejected(response, ChillieError::NoPendingHostTransfer);
+
+        // The new host must back the room on their own once the previous host leaves
+        let response = execute(&mut contract, "bob", ChillieOperation::AcceptHost { room_id: "room".to_string() });
+        assert_rejected(response, ChillieError::InsufficientStake { required });
+
+        stake(&mut contract, "bob", required);
+        let response = execute(&mut contract, "bob", ChillieOperation::AcceptHost { room_id: "room".to_string() });
+        assert!(matches!(response, ChillieResponse::HostTransferred { .. }));
+        let room = room(&contract, "room");
+        assert_eq!(room.host, user("bob"));
+        assert_eq!(room.pending_host, None);
+        assert_eq!(active_rooms(&contract, "bob"), vec!["room".to_string()]);
+        assert!(active_rooms(&contract, "alice").is_empty());
+    }
+
+    #[test]
+    fn test_declined_host_transfer_is_cleared() {
+        let mut contract = create_and_instantiate();
+        stake(&mut contract, "alice", TierConfig::default().podcast);
+        create_room(&mut contract, "alice", "room", RoomType::Podcast);
+
+        execute(&mut contract, "alice", ChillieOperation::TransferHost {
+            room_id: "room".to_string(),
+            new_host: user("bob"),
+        });
+        let response = execute(&mut contract, "bob", ChillieOperation::RevokeRole {
+            room_id: "room".to_string(),
+            user_id: user("bob"),
+        });
+        assert!(matches!(response, ChillieResponse::RoleRevoked { .. }));
+        assert_eq!(room(&contract, "room").pending_host, None);
+
+        let response = execute(&mut contract, "bob", ChillieOperation::AcceptHost { room_id: "room".to_string() });
+        assert_rejected(response, ChillieError::NoPendingHostTransfer);
+    }
+
+    #[test]
+    fn test_co_host_role_needs_acceptance_and_keeps_the_room_backed() {
+        let mut contract = create_and_instantiate();
+        let required = TierConfig::default().multi_stream;
+        stake(&mut contract, "alice", required);
+        stake(&mut contract, "bob", required);
+        create_room(&mut contract, "alice", "room", RoomType::MultiStream);
+
+        let response = execute(&mut contract, "bob", ChillieOperation::GrantRole {
+            room_id: "room".to_string(),
+            user_id: user("carol"),
+            role: RoomRole::Moderator,
+        });
+        assert_rejected(response, ChillieError::NotHost);
+
+        let response = execute(&mut contract, "alice", ChillieOperation::GrantRole {
+            room_id: "room".to_string(),
+            user_id: user("bob"),
+            role: RoomRole::CoHost,
+        });
+        assert!(matches!(response, ChillieResponse::CoHostInvited { .. }));
+        assert_eq!(room(&contract, "room").role_of(&user("bob")), None);
+
+        let response = execute(&mut contract, "bob", ChillieOperation::AcceptCoHost { room_id: "room".to_string() });
+        assert!(matches!(response, ChillieResponse::RoleGranted { .. }));
+        assert_eq!(active_rooms(&contract, "bob"), vec!["room".to_string()]);
+
+        // With the co-host covering the requirement, the host's stake is free again
+        let response = execute(&mut contract, "alice", ChillieOperation::Unstake { amount: required });
+        assert!(matches!(response, ChillieResponse::Unstaked { .. }));
+
+        let response = execute(&mut contract, "alice", ChillieOperation::GrantRole {
+            room_id: "room".to_string(),
+            user_id: user("bob"),
+            role: RoomRole::Moderator,
+        });
+        assert_rejected(response, ChillieError::InsufficientStake { required });
+        assert_eq!(room(&contract, "room").role_of(&user("bob")), Some(RoomRole::CoHost));
+        assert_eq!(active_rooms(&contract, "bob"), vec!["room".to_string()]);
+    }
+
+    #[test]
+    fn test_missed_heartbeats_lower_reputation_until_the_node_is_slashed() {
+        let mut contract = create_and_instantiate();
+        let config = SlashingConfig::default();
+        register_node(&mut contract, "alice");
+
+        // The interval in which the heartbeat is due does not count as missed
+        advance(&mut contract, config.heartbeat_interval_secs * 2 + 30);
+        let response = execute(&mut contract, "alice", ChillieOperation::Heartbeat);
+        let expected = MAX_REPUTATION - 2 * config.missed_heartbeat_penalty + config.heartbeat_reward;
+        assert!(
+            matches!(response, ChillieResponse::HeartbeatRecorded { reputation } if reputation == expected),
+            "Unexpected response: {:?}", response,
+        );
+
+        advance(&mut contract, config.heartbeat_interval_secs * 11);
+        let response = execute(&mut contract, "alice", ChillieOperation::Heartbeat);
+        let relay_stake = TierConfig::default().relay_node;
+        let penalty = config.penalty(relay_stake);
+        assert!(
+            matches!(response, ChillieResponse::NodeSlashed { amount, .. } if amount == penalty),
+            "Unexpected response: {:?}", response,
+        );
+        assert!(!contract.state.nodes.contains_key(&user("alice")).blocking_wait().expect("Failed to get node"));
+        assert_eq!(stake_of(&contract, "alice"), relay_stake.saturating_sub(penalty));
+
+        let response = execute(&mut contract, "alice", ChillieOperation::Heartbeat);
+        assert_rejected(response, ChillieError::NodeNotRegistered);
+    }
+
+    #[test]
+    fn test_unregistered_node_stays_slashable_until_its_stake_is_released() {
+        let mut contract = create_and_instantiate();
+        let config = SlashingConfig::default();
+        let relay_stake = TierConfig::default().relay_node;
+        register_node(&mut contract, "alice");
+        register_node(&mut contract, "bob");
+
+        let response = execute(&mut contract, "alice", ChillieOperation::UnregisterNode);
+        assert!(matches!(response, ChillieResponse::NodeUnregistered));
+        let response = execute(&mut contract, "alice", ChillieOperation::Unstake { amount: relay_stake });
+        assert_rejected(response, ChillieError::StakeLocked { locked: relay_stake });
+
+        // Admin reports are penalized at once; the third one takes the reputation below the threshold
+        let mut slashed = None;
+        for _ in 0..3 {
+            let response = execute(&mut contract, "admin", ChillieOperation::ReportNode {
+                node: user("alice"),
+                evidence: "dropped relayed streams".to_string(),
+            });
+            let ChillieResponse::NodeReported { slashed: reported, .. } = response else {
+                panic!("Unexpected response: {:?}", response);
+            };
+            slashed = reported;
+        }
+        assert_eq!(slashed, Some(config.penalty(relay_stake)));
+        assert_eq!(stake_of(&contract, "alice"), relay_stake.saturating_sub(config.penalty(relay_stake)));
+        assert!(contract.state.node_exits.get(&user("alice")).blocking_wait().expect("Failed to get node exit").is_none());
+
+        // Once the operator's stake is released, the node can no longer be reported
+        let response = execute(&mut contract, "bob", ChillieOperation::UnregisterNode);
+        assert!(matches!(response, ChillieResponse::NodeUnregistered));
+        advance(&mut contract, UNBONDING_PERIOD_SECS);
+        let response = execute(&mut contract, "admin", ChillieOperation::ReportNode {
+            node: user("bob"),
+            evidence: "dropped relayed streams".to_string(),
+        });
+        assert_rejected(response, ChillieError::NodeNotRegistered);
+    }
+
+    #[test]
+    fn test_unstaked_tokens_are_claimable_after_the_unbonding_period() {
+        let mut contract = create_and_instantiate();
+        let initial_balance = contract.runtime.owner_balance(owner("alice"));
+        stake(&mut contract, "alice", Amount::from_tokens(100));
+        assert_eq!(contract.runtime.owner_balance(owner("alice")), initial_balance.saturating_sub(Amount::from_tokens(100)));
+
+        let response = execute(&mut contract, "alice", ChillieOperation::Unstake { amount: Amount::from_tokens(101) });
+        assert_rejected(response, ChillieError::ExceedsStake);
+
+        let response = execute(&mut contract, "alice", ChillieOperation::Unstake { amount: Amount::from_tokens(40) });
+        let expected_release = Timestamp::from(0).saturating_add(TimeDelta::from_secs(UNBONDING_PERIOD_SECS));
+        assert!(
+            matches!(
+                response,
+                ChillieResponse::Unstaked { new_balance, release_at }
+                    if new_balance == Amount::from_tokens(60) && release_at == expected_release
+            ),
+            "Unexpected response: {:?}", response,
+        );
+        assert_eq!(stake_of(&contract, "alice"), Amount::from_tokens(60));
+
+        let response = execute(&mut contract, "alice", ChillieOperation::ClaimUnstaked);
+        assert_rejected(response, ChillieError::NothingToClaim);
+
+        advance(&mut contract, UNBONDING_PERIOD_SECS);
+        let response = execute(&mut contract, "alice", ChillieOperation::ClaimUnstaked);
+        assert!(
+            matches!(response, ChillieResponse::UnstakeClaimed { amount } if amount == Amount::from_tokens(40)),
+            "Unexpected response: {:?}", response,
+        );
+        assert_eq!(contract.runtime.owner_balance(owner("alice")), initial_balance.saturating_sub(Amount::from_tokens(60)));
+
+        let response = execute(&mut contract, "alice", ChillieOperation::ClaimUnstaked);
+        assert_rejected(response, ChillieError::NothingToClaim);
+    }
+
+    #[test]
+    fn test_expired_rooms_close_and_release_their_stake() {
+        let mut contract = create_and_instantiate();
+        let required = TierConfig::default().podcast;
+        stake(&mut contract, "alice", required);
+        let ends_at = Timestamp::from(0).saturating_add(TimeDelta::from_secs(3600));
+        for room_id in ["talk", "show"] {
+            let response = execute(&mut contract, "alice", ChillieOperation::CreateRoom {
+                room_id: room_id.to_string(),
+                room_type: RoomType::Podcast,
+                dedicated_chain: false,
+                starts_at: None,
+                ends_at: Some(ends_at),
+                metadata: RoomMetadata::default(),
+            });
+            assert!(matches!(response, ChillieResponse::RoomCreated { .. }), "Room creation failed: {:?}", response);
+        }
+
+        let response = execute(&mut contract, "bob", ChillieOperation::JoinRoom {
+            room_id: "talk".to_string(),
+            invite_code: None,
+        });
+        assert!(matches!(response, ChillieResponse::ParticipantJoined { .. }));
+        let response = execute(&mut contract, "alice", ChillieOperation::Unstake { amount: required });
+        assert_rejected(response, ChillieError::StakeLocked { locked: required });
+
+        advance(&mut contract, 3600);
+        let response = execute(&mut contract, "carol", ChillieOperation::JoinRoom {
+            room_id: "talk".to_string(),
+            invite_code: None,
+        });
+        assert!(matches!(response, ChillieResponse::RoomExpired { .. }), "Unexpected response: {:?}", response);
+        let talk = room(&contract, "talk");
+        assert!(!talk.is_active);
+        assert!(talk.participants.is_empty());
+        assert_eq!(active_rooms(&contract, "alice"), vec!["show".to_string()]);
+
+        // Unstaking closes the host's other expired room instead of keeping its stake locked
+        let response = execute(&mut contract, "alice", ChillieOperation::Unstake { amount: required });
+        assert!(matches!(response, ChillieResponse::Unstaked { .. }), "Unexpected response: {:?}", response);
+        assert!(!room(&contract, "show").is_active);
+        assert!(active_rooms(&contract, "alice").is_empty());
+    }
+
+    #[test]
+    fn test_legacy_stake_is_rejected_without_migrating_the_holder() {
+        let mut contract = create_and_instantiate();
+        register_node(&mut contract, "bob");
+        stake(&mut contract, "alice", Amount::from_tokens(100));
+        let new_token = SupportedToken::Custom(ApplicationId::new(CryptoHash::test_hash("token")));
+        let response = execute(&mut contract, "admin", ChillieOperation::SetStakingToken { token: new_token });
+        assert!(matches!(response, ChillieResponse::StakingTokenChanged { .. }), "Unexpected response: {:?}", response);
+
+        let response = execute(&mut contract, "alice", ChillieOperation::Stake { amount: Amount::from_tokens(10) });
+        assert_rejected(response, ChillieError::LegacyStakeToken);
+        let response = execute(&mut contract, "alice", ChillieOperation::Delegate {
+            node: user("bob"),
+            amount: Amount::from_tokens(10),
+        });
+        assert_rejected(response, ChillieError::LegacyStakeToken);
+
+        let alice = user("alice");
+        assert!(contract.state.legacy_stake_tokens.get(&alice).blocking_wait().expect("Failed to get stake token").is_none());
+        assert!(contract.state.stake_token_switches_seen.get(&alice).blocking_wait().expect("Failed to get switches").is_none());
+        assert_eq!(contract.state.stake_token_of(&alice).blocking_wait().expect("Failed to get stake token"), SupportedToken::Native);
+        assert_eq!(stake_of(&contract, "alice"), Amount::from_tokens(100));
+
+        // The legacy stake is still withdrawn in the token it was made in
+        let initial_balance = contract.runtime.owner_balance(owner("alice"));
+        execute(&mut contract, "alice", ChillieOperation::Unstake { amount: Amount::from_tokens(100) });
+        advance(&mut contract, UNBONDING_PERIOD_SECS);
+        let response = execute(&mut contract, "alice", ChillieOperation::ClaimUnstaked);
+        assert!(matches!(response, ChillieResponse::UnstakeClaimed { .. }), "Unexpected response: {:?}", response);
+        assert_eq!(
+            contract.runtime.owner_balance(owner("alice")),
+            initial_balance.saturating_add(Amount::from_tokens(100)),
+        );
+        assert!(!contract.state.holds_legacy_stake(&alice).blocking_wait().expect("Failed to get stake token"));
+    }
+
+    #[test]
+    fn test_delegators_share_node_rewards_after_the_commission() {
+        let mut contract = create_and_instantiate();
+        let response = execute(&mut contract, "admin", ChillieOperation::UpdateRewardConfig {
+            config: RewardConfig { epoch_duration_secs: 60, emission_per_epoch: Amount::from_tokens(100) },
+        });
+        assert!(matches!(response, ChillieResponse::RewardConfigUpdated));
+
+        stake(&mut contract, "bob", Amount::from_tokens(4000));
+        let response = execute(&mut contract, "bob", ChillieOperation::RegisterNode {
+            address: "127.0.0.1:8080".to_string(),
+            region: "global".to_string(),
+            bandwidth_mbps: 1000,
+            commission_percent: 10,
+        });
+        assert!(matches!(response, ChillieResponse::NodeRegistered { .. }));
+        let response = execute(&mut contract, "alice", ChillieOperation::Delegate {
+            node: user("bob"),
+            amount: Amount::from_tokens(1000),
+        });
+        assert!(matches!(response, ChillieResponse::Delegated { .. }), "Unexpected response: {:?}", response);
+        execute(&mut contract, "carol", ChillieOperation::FundRewardPool { amount: Amount::from_tokens(100) });
+
+        advance(&mut contract, 60);
+        let response = execute(&mut contract, "carol", ChillieOperation::DistributeRewards);
+        assert!(
+            matches!(response, ChillieResponse::RewardsDistributed { epochs: 1, amount } if amount == Amount::from_tokens(100)),
+            "Unexpected response: {:?}", response,
+        );
+
+        // 10 tokens of commission, then 90 shared between 4000 own and 1000 delegated tokens
+        let rewards = |contract: &ChillieContract, name: &str| {
+            contract.state.rewards.get(&user(name)).blocking_wait().expect("Failed to get rewards").unwrap_or_default()
+        };
+        assert_eq!(rewards(&contract, "alice"), Amount::from_tokens(18));
+        assert_eq!(rewards(&contract, "bob"), Amount::from_tokens(82));
+
+        let initial_balance = contract.runtime.owner_balance(owner("alice"));
+        let response = execute(&mut contract, "alice", ChillieOperation::ClaimNodeRewards);
+        assert!(matches!(response, ChillieResponse::NodeRewardsClaimed { .. }));
+        assert_eq!(
+            contract.runtime.owner_balance(owner("alice")),
+            initial_balance.saturating_add(Amount::from_tokens(18)),
+        );
+        assert_eq!(rewards(&contract, "alice"), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_slashed_legacy_stake_is_credited_in_its_own_token() {
+        let mut contract = create_and_instantiate();
+        let response = execute(&mut contract, "admin", ChillieOperation::UpdateSlashingConfig {
+            config: SlashingConfig { slash_percent: 100, ..SlashingConfig::default() },
+        });
+        assert!(matches!(response, ChillieResponse::SlashingConfigUpdated));
+        register_node(&mut contract, "bob");
+        let new_token = SupportedToken::Custom(ApplicationId::new(CryptoHash::test_hash("token")));
+        execute(&mut contract, "admin", ChillieOperation::SetStakingToken { token: new_token });
+
+        for _ in 0..3 {
+            execute(&mut contract, "admin", ChillieOperation::ReportNode {
+                node: user("bob"),
+                evidence: "dropped relayed streams".to_string(),
+            });
+        }
+
+        // The whole stake is taken, so bob no longer holds anything in the replaced token
+        let relay_stake = TierConfig::default().relay_node;
+        assert_eq!(stake_of(&contract, "bob"), Amount::ZERO);
+        let treasury = |contract: &ChillieContract, token: SupportedToken| {
+            contract.state.treasury.get(&token).blocking_wait().expect("Failed to get treasury").unwrap_or_default()
+        };
+        assert_eq!(treasury(&contract, SupportedToken::Native), relay_stake);
+        assert_eq!(treasury(&contract, new_token), Amount::ZERO);
+    }
+}
@@ -1,8 +1,18 @@
 // Chillie - Decentralized Video Meeting Platform
 // Room Manager with Staking Tier System
 
-use linera_sdk::linera_base_types::{ContractAbi, ServiceAbi, Amount};
+use async_graphql::{Enum, InputObject, Request, Response, SimpleObject};
+use linera_sdk::{
+    graphql::GraphQLMutationRoot,
+    linera_base_types::{
+        Account, AccountOwner, ApplicationId, ChainId, ContractAbi, ServiceAbi, Amount, StreamName, Timestamp,
+    },
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Shared with the payment processor, which sets platform fees by the host's tier
+pub use chillie_payment_processor::StakingTier;
 
 pub struct ChillieAbi;
 
@@ -12,8 +22,8 @@ impl ContractAbi for ChillieAbi {
 }
 
 impl ServiceAbi for ChillieAbi {
-    type Query = ChillieRequest;
-    type QueryResponse = ChillieQueryResponse;
+    type Query = Request;
+    type QueryResponse = Response;
 }
 
 /// Unique identifier for a room
@@ -25,6 +35,87 @@ pub type TokenId = linera_sdk::linera_base_types::ApplicationId;
 /// Node network address (e.g., IP:Port or Domain)
 pub type NodeAddress = String;
 
+/// Time unstaked funds stay in the withdrawal queue before they can be claimed
+pub const UNBONDING_PERIOD_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Reputation a relay node starts with, and the most it can reach
+pub const MAX_REPUTATION: u64 = 100;
+
+/// Parameters of the relay node reputation and slashing scheme
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, SimpleObject, InputObject)]
+#[graphql(input_name = "SlashingConfigInput")]
+pub struct SlashingConfig {
+    /// Expected time between two heartbeats of a node
+    pub heartbeat_interval_secs: u64,
+    /// Reputation lost for every missed heartbeat interval
+    pub missed_heartbeat_penalty: u64,
+    /// Reputation regained with every on-time heartbeat
+    pub heartbeat_reward: u64,
+    /// Reputation lost when reports against a node reach the quorum
+    pub report_penalty: u64,
+    /// Number of distinct registered nodes that must report a node before it is penalized
+    pub report_quorum: usize,
+    /// Reputation below which a node is slashed and unregistered
+    pub slash_threshold: u64,
+    /// Share of the RelayNode stake requirement taken when slashing, in percent
+    pub slash_percent: u8,
+}
+
+impl Default for SlashingConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval_secs: 60,
+            missed_heartbeat_penalty: 5,
+            heartbeat_reward: 1,
+            report_penalty: 20,
+            report_quorum: 3,
+            slash_threshold: 50,
+            slash_percent: 10,
+        }
+    }
+}
+
+impl SlashingConfig {
+    /// Whether heartbeats are expected, reports need a reporter and penalties stay within bounds
+    pub fn is_valid(&self) -> bool {
+        self.heartbeat_interval_secs > 0
+            && self.report_quorum > 0
+            && self.slash_threshold <= MAX_REPUTATION
+            && self.slash_percent <= 100
+    }
+
+    /// Part of a stake taken when slashing
+    pub fn penalty(&self, stake: Amount) -> Amount {
+        Amount::from_attos(u128::from(stake).saturating_mul(u128::from(self.slash_percent.min(100))) / 100)
+    }
+}
+
+/// Parameters of relay node reward emission
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, SimpleObject, InputObject)]
+#[graphql(input_name = "RewardConfigInput")]
+pub struct RewardConfig {
+    /// Length of a reward epoch
+    pub epoch_duration_secs: u64,
+    /// Amount released from the reward pool to healthy nodes per epoch
+    pub emission_per_epoch: Amount,
+}
+
+impl Default for RewardConfig {
+    fn default() -> Self {
+        Self {
+            epoch_duration_secs: 24 * 60 * 60,
+            emission_per_epoch: Amount::from_tokens(100),
+        }
+    }
+}
+
+impl RewardConfig {
+    /// Whether epochs have a length
+    pub fn is_valid(&self) -> bool {
+        self.epoch_duration_secs > 0
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
 pub enum SupportedToken {
     #[default]
@@ -32,28 +123,113 @@ pub enum SupportedToken {
     Custom(TokenId),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
-pub enum StakingTier {
-    Basic,      // 0 stake
-    Podcast,    // 100 stake
-    SoloStream, // 500 stake
-    MultiStream,// 2000 stake
-    RelayNode,  // 5000 stake
+async_graphql::scalar!(SupportedToken);
+
+/// Stream carrying room and participant events
+pub const ROOM_EVENTS_STREAM: &[u8] = b"rooms";
+/// Stream carrying relay node events
+pub const NODE_EVENTS_STREAM: &[u8] = b"nodes";
+/// Stream carrying stake balance changes
+pub const STAKE_EVENTS_STREAM: &[u8] = b"stakes";
+
+/// Events emitted by the room manager
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ChillieEvent {
+    RoomCreated { room_id: RoomId, host: UserId, room_type: RoomType },
+    RoomClosed { room_id: RoomId },
+    ParticipantJoined { room_id: RoomId, user_id: UserId },
+    ParticipantLeft { room_id: RoomId, user_id: UserId },
+    NodeRegistered { node: UserId, address: NodeAddress },
+    NodeUnregistered { node: UserId },
+    NodeSlashed { node: UserId, amount: Amount },
+    StakeChanged { user_id: UserId, stake: Amount },
+    /// A delegator's stake with a node changed; `amount` is the new delegated total
+    DelegationChanged { node: UserId, delegator: UserId, amount: Amount },
+    /// The payment processor rejected a room lifecycle change, which was applied to the room anyway
+    PaymentSyncFailed { room_id: RoomId, error: String },
+}
+
+impl ChillieEvent {
+    /// Returns the stream the event is published on
+    pub fn stream_name(&self) -> StreamName {
+        let name = match self {
+            ChillieEvent::RoomCreated { .. }
+            | ChillieEvent::RoomClosed { .. }
+            | ChillieEvent::ParticipantJoined { .. }
+            | ChillieEvent::ParticipantLeft { .. }
+            | ChillieEvent::PaymentSyncFailed { .. } => ROOM_EVENTS_STREAM,
+            ChillieEvent::NodeRegistered { .. }
+            | ChillieEvent::NodeUnregistered { .. }
+            | ChillieEvent::NodeSlashed { .. } => NODE_EVENTS_STREAM,
+            ChillieEvent::StakeChanged { .. } | ChillieEvent::DelegationChanged { .. } => STAKE_EVENTS_STREAM,
+        };
+        StreamName::from(name)
+    }
 }
 
-impl StakingTier {
-    pub fn required_stake(&self) -> Amount {
-        match self {
-            StakingTier::Basic => Amount::from_tokens(0),
-            StakingTier::Podcast => Amount::from_tokens(100),
-            StakingTier::SoloStream => Amount::from_tokens(500),
-            StakingTier::MultiStream => Amount::from_tokens(2000),
-            StakingTier::RelayNode => Amount::from_tokens(5000),
+/// Messages between the main chain, which keeps the room registry and stakes, and the
+/// dedicated chains of high-traffic rooms
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ChillieMessage {
+    /// Sets up a room on its freshly opened chain; sent tracked, so the main chain closes the room
+    /// again if the setup is rejected
+    InitRoomChain {
+        room_id: RoomId,
+        host: UserId,
+        host_tier: StakingTier,
+        room_type: RoomType,
+        required_stake: Amount,
+        relays: Vec<UserId>,
+        starts_at: Option<Timestamp>,
+        ends_at: Option<Timestamp>,
+    },
+    /// Pushes host, role and relay changes made on the main chain to the room chain
+    ///
+    /// Like the summaries and closings below, updates are sent untracked: the main chain's
+    /// state stays authoritative and the next update supersedes a rejected one.
+    RoomUpdated {
+        room_id: RoomId,
+        host: UserId,
+        host_tier: StakingTier,
+        roles: BTreeMap<UserId, RoomRole>,
+        relays: Vec<UserId>,
+    },
+    /// Reports the room's participant count and status back to the main chain
+    RoomSummary { room_id: RoomId, participant_count: usize, is_active: bool },
+    /// Closes the room on its chain after it was closed on the main chain
+    CloseRoom { room_id: RoomId },
+}
+
+/// Application parameters of the room manager
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChillieParameters {
+    /// Payment-processor application that receives room lifecycle changes
+    pub payment_processor: Option<ApplicationId>,
+    /// Native tokens a host pays to cover the fees of a room's dedicated chain
+    pub room_chain_balance: Amount,
+}
+
+impl Default for ChillieParameters {
+    fn default() -> Self {
+        Self {
+            payment_processor: None,
+            room_chain_balance: Amount::from_tokens(1),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+/// Configuration supplied when the room-manager application is created
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct InstantiationArgument {
+    /// Owner allowed to run admin operations; defaults to the creator
+    pub admin: Option<AccountOwner>,
+    pub staking_token: SupportedToken,
+    pub tier_config: TierConfig,
+    pub slashing_config: SlashingConfig,
+    pub reward_config: RewardConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Enum)]
 pub enum RoomType {
     PrivateMeeting, // Requires Basic
     Podcast,        // Requires Podcast
@@ -62,6 +238,7 @@ pub enum RoomType {
 }
 
 impl RoomType {
+    /// Default tier of the room type; deployments override it through `TierConfig`
     pub fn required_tier(&self) -> StakingTier {
         match self {
             RoomType::PrivateMeeting => StakingTier::Basic,
@@ -70,50 +247,700 @@ impl RoomType {
             RoomType::MultiStream => StakingTier::MultiStream,
         }
     }
+
+    /// Number of relay nodes assigned to a room of this type
+    pub fn relay_count(&self) -> usize {
+        match self {
+            RoomType::PrivateMeeting | RoomType::Podcast => 0,
+            RoomType::SoloStream => 1,
+            RoomType::MultiStream => 2,
+        }
+    }
+
+    /// Whether rooms of this type may run on a dedicated chain
+    pub fn supports_dedicated_chain(&self) -> bool {
+        matches!(self, RoomType::SoloStream | RoomType::MultiStream)
+    }
+
+    /// Maximum number of participants a room of this type admits
+    pub fn max_participants(&self) -> usize {
+        match self {
+            RoomType::PrivateMeeting => 10,
+            RoomType::Podcast => 50,
+            RoomType::SoloStream => 500,
+            RoomType::MultiStream => 2000,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Longest room title, in characters
+pub const MAX_TITLE_LENGTH: usize = 100;
+/// Longest room description, in characters
+pub const MAX_DESCRIPTION_LENGTH: usize = 2000;
+/// Most tags a room may carry
+pub const MAX_TAGS: usize = 10;
+/// Longest tag, in characters
+pub const MAX_TAG_LENGTH: usize = 32;
+/// Longest Walrus blob id accepted for thumbnails
+pub const MAX_BLOB_ID_LENGTH: usize = 64;
+
+/// Longest relay node address, in bytes
+pub const MAX_NODE_ADDRESS_LENGTH: usize = 256;
+
+/// Walrus blob identifier
+pub type BlobId = String;
+
+/// Whether `blob_id` looks like a Walrus blob id: URL-safe base64 of bounded length
+pub fn is_valid_blob_id(blob_id: &str) -> bool {
+    !blob_id.is_empty()
+        && blob_id.len() <= MAX_BLOB_ID_LENGTH
+        && blob_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Whether `address` is a `host:port` pair a relay node can be reached at
+///
+/// Hosts are IP addresses, bracketed IPv6 addresses or DNS names; port 0 is rejected.
+pub fn is_valid_node_address(address: &str) -> bool {
+    if address.len() > MAX_NODE_ADDRESS_LENGTH {
+        return false;
+    }
+    if let Ok(socket_address) = address.parse::<std::net::SocketAddr>() {
+        return socket_address.port() != 0;
+    }
+
+    let Some((host, port)) = address.rsplit_once(':') else {
+        return false;
+    };
+    port.parse::<u16>().is_ok_and(|port| port != 0)
+        && !host.is_empty()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Recording of a room stored on Walrus
+#[derive(Debug, Serialize, Deserialize, Clone, SimpleObject)]
+pub struct Recording {
+    pub blob_id: BlobId,
+    pub duration_secs: u64,
+    pub size_bytes: u64,
+    /// Host or relay node that attached the recording
+    pub attached_by: UserId,
+    pub attached_at: Timestamp,
+    /// Price of replay access charged through the payment processor; `None` for free replays
+    pub replay_price: Option<Amount>,
+}
+
+/// Descriptive room information shown in room listings
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, SimpleObject, InputObject)]
+#[graphql(input_name = "RoomMetadataInput")]
+pub struct RoomMetadata {
+    pub title: String,
+    pub description: String,
+    /// Lowercase search tags made of letters, digits and dashes
+    pub tags: Vec<String>,
+    /// Language code such as `en` or `pt-BR`
+    pub language: Option<String>,
+    /// Walrus blob id of the room thumbnail
+    pub thumbnail_blob: Option<BlobId>,
+}
+
+impl RoomMetadata {
+    /// Trims the text fields, lowercases and deduplicates tags, and checks every field's limits
+    pub fn normalize(mut self) -> Result<Self, &'static str> {
+        self.title = self.title.trim().to_string();
+        self.description = self.description.trim().to_string();
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags {
+            let tag = tag.trim().to_lowercase();
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        self.tags = tags;
+
+        if self.title.chars().count() > MAX_TITLE_LENGTH {
+            return Err("Room title is too long");
+        }
+        if self.description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err("Room description is too long");
+        }
+        if self.tags.len() > MAX_TAGS {
+            return Err("Room has too many tags");
+        }
+        let valid_tag = |tag: &String| {
+            !tag.is_empty()
+                && tag.len() <= MAX_TAG_LENGTH
+                && tag.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        };
+        if !self.tags.iter().all(valid_tag) {
+            return Err("Tags must be 1 to 32 lowercase letters, digits or dashes");
+        }
+        if let Some(language) = &self.language {
+            let mut parts = language.split('-');
+            let primary = parts.next().unwrap_or_default();
+            let valid = (2..=3).contains(&primary.len())
+                && primary.chars().all(|c| c.is_ascii_alphabetic())
+                && parts.all(|part| (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric()));
+            if !valid {
+                return Err("Language must be a code such as en or pt-BR");
+            }
+        }
+        if self.thumbnail_blob.as_deref().is_some_and(|blob| !is_valid_blob_id(blob)) {
+            return Err("Thumbnail must be a Walrus blob id");
+        }
+
+        Ok(self)
+    }
+}
+
+/// Stake thresholds per tier and the tier each room type requires
+///
+/// Changes apply to rooms and node registrations created afterwards; existing ones keep the
+/// requirement recorded when they were created.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, SimpleObject, InputObject)]
+#[graphql(input_name = "TierConfigInput")]
+pub struct TierConfig {
+    pub basic: Amount,
+    pub podcast: Amount,
+    pub solo_stream: Amount,
+    pub multi_stream: Amount,
+    pub relay_node: Amount,
+    /// Part of the RelayNode requirement an operator must stake themselves
+    pub relay_node_self_stake: Amount,
+    pub private_meeting_tier: StakingTier,
+    pub podcast_tier: StakingTier,
+    pub solo_stream_tier: StakingTier,
+    pub multi_stream_tier: StakingTier,
+}
+
+impl Default for TierConfig {
+    fn default() -> Self {
+        Self {
+            basic: StakingTier::Basic.required_stake(),
+            podcast: StakingTier::Podcast.required_stake(),
+            solo_stream: StakingTier::SoloStream.required_stake(),
+            multi_stream: StakingTier::MultiStream.required_stake(),
+            relay_node: StakingTier::RelayNode.required_stake(),
+            relay_node_self_stake: Amount::from_tokens(500),
+            private_meeting_tier: RoomType::PrivateMeeting.required_tier(),
+            podcast_tier: RoomType::Podcast.required_tier(),
+            solo_stream_tier: RoomType::SoloStream.required_tier(),
+            multi_stream_tier: RoomType::MultiStream.required_tier(),
+        }
+    }
+}
+
+impl TierConfig {
+    pub fn required_stake(&self, tier: StakingTier) -> Amount {
+        match tier {
+            StakingTier::Basic => self.basic,
+            StakingTier::Podcast => self.podcast,
+            StakingTier::SoloStream => self.solo_stream,
+            StakingTier::MultiStream => self.multi_stream,
+            StakingTier::RelayNode => self.relay_node,
+        }
+    }
+
+    pub fn required_tier(&self, room_type: RoomType) -> StakingTier {
+        match room_type {
+            RoomType::PrivateMeeting => self.private_meeting_tier,
+            RoomType::Podcast => self.podcast_tier,
+            RoomType::SoloStream => self.solo_stream_tier,
+            RoomType::MultiStream => self.multi_stream_tier,
+        }
+    }
+
+    /// Stake a host needs to open a room of the given type
+    pub fn room_stake(&self, room_type: RoomType) -> Amount {
+        self.required_stake(self.required_tier(room_type))
+    }
+
+    /// Returns the highest tier whose threshold a stake reaches
+    pub fn tier_for_stake(&self, stake: Amount) -> StakingTier {
+        [StakingTier::RelayNode, StakingTier::MultiStream, StakingTier::SoloStream, StakingTier::Podcast]
+            .into_iter()
+            .find(|tier| stake >= self.required_stake(*tier))
+            .unwrap_or(StakingTier::Basic)
+    }
+
+    /// Checks that thresholds never decrease from one tier to the next
+    pub fn is_valid(&self) -> bool {
+        self.basic <= self.podcast
+            && self.podcast <= self.solo_stream
+            && self.solo_stream <= self.multi_stream
+            && self.multi_stream <= self.relay_node
+            && self.relay_node_self_stake <= self.relay_node
+    }
+}
+
+/// Role a user holds in a room
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum RoomRole {
+    /// Owns the room; can manage roles, settings and participants
+    Host,
+    /// Shares hosting duties in MultiStream rooms; their stake backs the room's tier
+    CoHost,
+    /// Can remove participants
+    Moderator,
+}
+
+#[derive(Debug, Serialize, Deserialize, GraphQLMutationRoot)]
 pub enum ChillieOperation {
     Stake { amount: Amount },
+    /// Moves stake into the withdrawal queue; it can be claimed once the unbonding period ends
     Unstake { amount: Amount },
-    CreateRoom { room_id: RoomId, room_type: RoomType },
+    /// Pays out every queued withdrawal whose unbonding period has ended
+    ClaimUnstaked,
+    /// Creates a room; stream rooms may run on a `dedicated_chain` that handles their
+    /// participants and payments while this chain keeps the registry entry
+    ///
+    /// Scheduled rooms only admit participants between `starts_at` and `ends_at`, and are
+    /// closed by the first operation that touches them after they end.
+    CreateRoom {
+        room_id: RoomId,
+        room_type: RoomType,
+        dedicated_chain: bool,
+        starts_at: Option<Timestamp>,
+        ends_at: Option<Timestamp>,
+        metadata: RoomMetadata,
+    },
     CloseRoom { room_id: RoomId },
-    RegisterNode { address: NodeAddress },
+    /// Replaces the room's title, description, tags, language and thumbnail
+    UpdateRoomMetadata { room_id: RoomId, metadata: RoomMetadata },
+    /// Links a Walrus recording to the room; only the host or an assigned relay node may call it
+    AttachRecording {
+        room_id: RoomId,
+        blob_id: BlobId,
+        duration_secs: u64,
+        size_bytes: u64,
+        replay_price: Option<Amount>,
+    },
+    /// Joins a room; `invite_code` is required for invite-only rooms unless the signer is allowlisted
+    JoinRoom { room_id: RoomId, invite_code: Option<String> },
+    LeaveRoom { room_id: RoomId },
+    /// Removes a participant and bars them from rejoining
+    KickParticipant { room_id: RoomId, user_id: UserId },
+    /// Replaces the admission rules of a room; `None` for both makes the room open
+    UpdateRoomAccess { room_id: RoomId, allowlist: Option<Vec<UserId>>, invite_code: Option<String> },
+    /// Gives a user the moderator role in a room, or invites them to co-host it
+    ///
+    /// Co-hosts back the room with their own stake, so the co-host role only takes effect once
+    /// the invited user accepts it with `AcceptCoHost`.
+    GrantRole { room_id: RoomId, user_id: UserId, role: RoomRole },
+    /// Accepts the signer's pending co-host invite
+    AcceptCoHost { room_id: RoomId },
+    /// Removes a user's role, pending co-host invite or pending host transfer; users may always
+    /// give up their own
+    RevokeRole { room_id: RoomId, user_id: UserId },
+    /// Offers the room to another user, who takes it over with `AcceptHost`
+    ///
+    /// The new host backs the room with their own stake, so nothing changes until they accept;
+    /// the previous host then keeps no role.
+    TransferHost { room_id: RoomId, new_host: UserId },
+    /// Accepts the signer's pending host transfer
+    AcceptHost { room_id: RoomId },
+    /// Registers the signer as a relay node advertising its region and bandwidth
+    ///
+    /// The operator must stake at least `relay_node_self_stake` themselves; stake delegated to the
+    /// node counts toward the rest of the RelayNode requirement, and the node only relays rooms and
+    /// earns rewards while the requirement is met. The operator keeps `commission_percent` of the
+    /// node's rewards and shares the rest with its delegators.
+    RegisterNode { address: NodeAddress, region: String, bandwidth_mbps: u64, commission_percent: u8 },
+    /// Removes the signer's node after applying pending penalties; its stake stays locked for the
+    /// unbonding period
     UnregisterNode,
+    /// Delegates staking tokens to a registered relay node; delegations are slashed with the node
+    Delegate { node: UserId, amount: Amount },
+    /// Moves delegated stake into the withdrawal queue
+    Undelegate { node: UserId, amount: Amount },
+    /// Proves liveness of the signer's relay node
+    Heartbeat,
+    /// Reports misbehaviour of a relay node; only registered nodes and the admin may report
+    ///
+    /// A report by the admin is penalized at once without waiting for a quorum. Unregistered
+    /// nodes stay reportable until their operator's stake finishes unbonding.
+    ReportNode { node: UserId, evidence: String },
+    /// Replaces unhealthy relays of a room and fills its free relay slots
+    RebalanceRoom { room_id: RoomId },
+    /// Adds staking tokens to the relay reward pool, e.g. a share of payment revenue
+    FundRewardPool { amount: Amount },
+    /// Releases the emission of every elapsed epoch to healthy nodes
+    DistributeRewards,
+    /// Pays out the signer's accrued relay rewards, as operator or delegator
+    ClaimNodeRewards,
+    /// Replaces the staking tier configuration; admin only
+    UpdateTierConfig { config: TierConfig },
+    /// Replaces the relay reward emission parameters; admin only
+    ///
+    /// Epochs elapsed under the previous parameters are distributed first.
+    UpdateRewardConfig { config: RewardConfig },
+    /// Replaces the reputation and slashing parameters; admin only
+    UpdateSlashingConfig { config: SlashingConfig },
+    /// Switches the token used for new stakes; admin only
+    ///
+    /// Existing stakes and queued withdrawals stay in the token they were made in. The reward
+    /// pool and unclaimed rewards must be empty.
+    SetStakingToken { token: SupportedToken },
+    /// Stops or resumes the creation of new rooms; admin only
+    SetRoomCreationPaused { paused: bool },
+    /// Closes a room regardless of its hosts; admin only
+    ForceCloseRoom { room_id: RoomId },
+    /// Pays slashed stake out of the treasury; admin only
+    WithdrawTreasury { token: SupportedToken, amount: Amount, recipient: Account },
+    /// Mirrors the rooms of the room manager on another chain through its event stream; admin only
+    FollowChain { chain_id: ChainId },
+    /// Stops mirroring the rooms of another chain; admin only
+    UnfollowChain { chain_id: ChainId },
+    /// Adds up to `limit` rooms after `after` to the room indexes, for rooms created before the
+    /// indexes existed; admin only
+    IndexRooms { after: Option<RoomId>, limit: u32 },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ChillieResponse {
     Ok,
     Staked { new_balance: Amount },
-    Unstaked { new_balance: Amount },
-    RoomCreated { room_id: RoomId },
+    Unstaked { new_balance: Amount, release_at: Timestamp },
+    UnstakeClaimed { amount: Amount },
+    RoomCreated { room_id: RoomId, chain_id: Option<ChainId> },
     RoomClosed { room_id: RoomId },
+    /// The room had passed its end time and was closed instead of running the operation
+    RoomExpired { room_id: RoomId },
+    ParticipantJoined { room_id: RoomId, participant_count: usize },
+    ParticipantLeft { room_id: RoomId },
+    ParticipantKicked { room_id: RoomId, user_id: UserId },
+    RoomAccessUpdated { room_id: RoomId },
+    RoomMetadataUpdated { room_id: RoomId },
+    RecordingAttached { room_id: RoomId, blob_id: BlobId },
+    RoleGranted { room_id: RoomId, user_id: UserId, role: RoomRole },
+    CoHostInvited { room_id: RoomId, user_id: UserId },
+    RoleRevoked { room_id: RoomId, user_id: UserId },
+    HostTransferProposed { room_id: RoomId, new_host: UserId },
+    HostTransferred { room_id: RoomId, new_host: UserId },
     NodeRegistered { address: NodeAddress },
     NodeUnregistered,
+    Delegated { node: UserId, delegated: Amount },
+    Undelegated { node: UserId, delegated: Amount, release_at: Timestamp },
+    HeartbeatRecorded { reputation: u64 },
+    NodeSlashed { node: UserId, amount: Amount },
+    NodeReported { node: UserId, reputation: u64, slashed: Option<Amount> },
+    RelaysAssigned { room_id: RoomId, relays: Vec<UserId> },
+    RewardPoolFunded { pool: Amount },
+    RewardsDistributed { epochs: u64, amount: Amount },
+    NodeRewardsClaimed { amount: Amount },
+    TierConfigUpdated,
+    RewardConfigUpdated,
+    SlashingConfigUpdated,
+    StakingTokenChanged { token: SupportedToken },
+    RoomCreationPaused { paused: bool },
+    TreasuryWithdrawn { amount: Amount },
+    ChainFollowed { chain_id: ChainId },
+    ChainUnfollowed { chain_id: ChainId },
+    /// Cursor to pass as `after` to index the next rooms; `None` once every room is indexed
+    RoomsIndexed { next_cursor: Option<RoomId> },
+    /// The operation was rejected and changed nothing
+    Error { error: ChillieError },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum ChillieRequest {
-    GetUserInfo { user_id: UserId },
-    GetRoomInfo { room_id: RoomId },
+/// Reasons an operation can be rejected
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum ChillieError {
+    Unauthenticated,
+    NotAdmin,
+    UserNotFound,
+    /// The stake backing the room or node would be below `required`
+    InsufficientStake { required: Amount },
+    /// The remaining stake would not cover the stake locked by rooms and node registration
+    StakeLocked { locked: Amount },
+    ExceedsStake,
+    /// The user's stake is still held in a previous staking token
+    LegacyStakeToken,
+    NothingToClaim,
+    InvalidAmount,
+    RoomCreationPaused,
+    RoomAlreadyExists,
+    RoomNotFound,
+    RoomInactive,
+    RoomNotStarted,
+    RoomFull,
+    DedicatedChainNotSupported,
+    InvalidSchedule,
+    InvalidMetadata { reason: String },
+    InvalidInviteCode,
+    AlreadyInRoom,
+    NotInRoom,
+    NotAdmitted,
+    NotHost,
+    /// Only the host or co-hosts may perform the operation
+    NotRoomManager,
+    /// Only the host, co-hosts or moderators may perform the operation
+    NotModerator,
+    NotHostOrRelay,
+    /// Users holding a room role cannot be kicked
+    ProtectedParticipant,
+    AlreadyHost,
+    /// The role cannot be granted in this room
+    InvalidRole,
+    NoRevocableRole,
+    /// The signer has no pending co-host invite for the room
+    NotInvited,
+    /// The signer has no pending host transfer for the room
+    NoPendingHostTransfer,
+    /// The room's participants are handled by its dedicated chain
+    RoomHostedElsewhere { chain_id: ChainId },
+    /// The operation changes roles, relays or stake, which are only managed on the main chain
+    MainChainOnly,
+    InvalidBlobId,
+    InvalidReplayPrice,
+    RecordingAlreadyAttached,
+    NodeAlreadyRegistered,
+    NodeNotRegistered,
+    InvalidRegion,
+    InvalidCommission,
+    /// The node address is not a `host:port` pair
+    InvalidNodeAddress,
+    InvalidEvidence,
+    SelfReport,
+    AlreadyReported,
+    /// Only registered nodes and the admin may report nodes
+    NotReporter,
+    SelfDelegation,
+    RewardsAlreadyDistributed,
+    InvalidTierConfig,
+    /// Reward epochs must have a length
+    InvalidRewardConfig,
+    /// Slashing parameters are out of bounds
+    InvalidSlashingConfig,
+    SameStakingToken,
+    /// The reward pool or unclaimed rewards are not empty
+    OutstandingRewards,
+    OutstandingDelegations,
+    /// The chain is the current one, whose rooms are tracked directly
+    CannotFollowSelf,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum ChillieQueryResponse {
-    UserInfo(Option<UserView>),
-    RoomInfo(Option<RoomView>),
+impl std::fmt::Display for ChillieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChillieError::Unauthenticated => write!(f, "Authentication required"),
+            ChillieError::NotAdmin => write!(f, "Only the admin can perform this operation"),
+            ChillieError::UserNotFound => write!(f, "User not found"),
+            ChillieError::InsufficientStake { required } => write!(f, "Insufficient stake. Required: {}", required),
+            ChillieError::StakeLocked { locked } => {
+                write!(f, "Remaining balance insufficient for locked stake {}", locked)
+            }
+            ChillieError::ExceedsStake => write!(f, "Cannot unstake more than the staked balance"),
+            ChillieError::LegacyStakeToken => {
+                write!(f, "Stake is held in a previous staking token; unstake and claim it first")
+            }
+            ChillieError::NothingToClaim => write!(f, "Nothing to claim"),
+            ChillieError::InvalidAmount => write!(f, "Invalid amount"),
+            ChillieError::RoomCreationPaused => write!(f, "Room creation is paused"),
+            ChillieError::RoomAlreadyExists => write!(f, "Room already exists"),
+            ChillieError::RoomNotFound => write!(f, "Room not found"),
+            ChillieError::RoomInactive => write!(f, "Room is not active"),
+            ChillieError::RoomNotStarted => write!(f, "Room has not started yet"),
+            ChillieError::RoomFull => write!(f, "Room is full"),
+            ChillieError::DedicatedChainNotSupported => {
+                write!(f, "Only SoloStream and MultiStream rooms can run on a dedicated chain")
+            }
+            ChillieError::InvalidSchedule => write!(f, "Room must end in the future and start before it ends"),
+            ChillieError::InvalidMetadata { reason } => write!(f, "Invalid room metadata: {}", reason),
+            ChillieError::InvalidInviteCode => write!(f, "Invite code cannot be empty"),
+            ChillieError::AlreadyInRoom => write!(f, "Already in the room"),
+            ChillieError::NotInRoom => write!(f, "Not in the room"),
+            ChillieError::NotAdmitted => write!(f, "Not allowed to join this room"),
+            ChillieError::NotHost => write!(f, "Only the host can perform this operation"),
+            ChillieError::NotRoomManager => write!(f, "Only the host or co-hosts can perform this operation"),
+            ChillieError::NotModerator => {
+                write!(f, "Only the host, co-hosts or moderators can perform this operation")
+            }
+            ChillieError::NotHostOrRelay => {
+                write!(f, "Only the host or an assigned relay node can perform this operation")
+            }
+            ChillieError::ProtectedParticipant => write!(f, "Cannot kick a user holding a room role"),
+            ChillieError::AlreadyHost => write!(f, "User already hosts the room"),
+            ChillieError::InvalidRole => write!(f, "Role cannot be granted in this room"),
+            ChillieError::NoRevocableRole => write!(f, "User holds no revocable role"),
+            ChillieError::NotInvited => write!(f, "No pending co-host invite for this room"),
+            ChillieError::NoPendingHostTransfer => write!(f, "No pending host transfer for this room"),
+            ChillieError::RoomHostedElsewhere { chain_id } => write!(f, "Room is hosted on chain {}", chain_id),
+            ChillieError::MainChainOnly => write!(f, "Room roles and relays are managed on the main chain"),
+            ChillieError::InvalidBlobId => write!(f, "Recording must be a Walrus blob id"),
+            ChillieError::InvalidReplayPrice => {
+                write!(f, "Replay price must be positive; leave it unset for free replays")
+            }
+            ChillieError::RecordingAlreadyAttached => write!(f, "Recording already attached"),
+            ChillieError::NodeAlreadyRegistered => write!(f, "Node already registered"),
+            ChillieError::NodeNotRegistered => write!(f, "Node not registered"),
+            ChillieError::InvalidRegion => write!(f, "Region must be between 1 and 64 bytes"),
+            ChillieError::InvalidCommission => write!(f, "Commission must be at most 100 percent"),
+            ChillieError::InvalidNodeAddress => {
+                write!(f, "Node address must be a host and port, e.g. relay.example.com:443")
+            }
+            ChillieError::InvalidEvidence => write!(f, "Evidence must be between 1 and 1000 bytes"),
+            ChillieError::SelfReport => write!(f, "Nodes cannot report themselves"),
+            ChillieError::AlreadyReported => write!(f, "Node already reported by this reporter"),
+            ChillieError::NotReporter => write!(f, "Only registered nodes or the admin can report"),
+            ChillieError::SelfDelegation => write!(f, "Operators back their own node by staking"),
+            ChillieError::RewardsAlreadyDistributed => {
+                write!(f, "Rewards for the current epoch were already distributed")
+            }
+            ChillieError::InvalidTierConfig => {
+                write!(f, "Tier thresholds must not decrease from one tier to the next")
+            }
+            ChillieError::InvalidRewardConfig => write!(f, "Reward epochs must last at least one second"),
+            ChillieError::InvalidSlashingConfig => {
+                write!(f, "Heartbeat interval and report quorum must be positive, thresholds within bounds")
+            }
+            ChillieError::SameStakingToken => write!(f, "Token is already the staking token"),
+            ChillieError::OutstandingRewards => {
+                write!(f, "Reward pool and unclaimed rewards must be empty before switching tokens")
+            }
+            ChillieError::OutstandingDelegations => {
+                write!(f, "Delegations must be undelegated before switching tokens")
+            }
+            ChillieError::CannotFollowSelf => write!(f, "Rooms of this chain are already tracked"),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl std::error::Error for ChillieError {}
+
+/// Largest page returned by list queries
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Criteria for relay node discovery; unset fields match every node
+#[derive(Debug, Serialize, Deserialize, Clone, Default, InputObject)]
+pub struct NodeFilter {
+    pub min_reputation: Option<u64>,
+    /// Maximum time since the node's last heartbeat
+    pub max_heartbeat_age_secs: Option<u64>,
+    pub region: Option<String>,
+    pub min_bandwidth_mbps: Option<u64>,
+}
+
+/// Criteria for the room directory; unset fields match every room
+#[derive(Debug, Serialize, Deserialize, Clone, Default, InputObject)]
+pub struct RoomFilter {
+    /// Whether to list only active or only closed rooms
+    pub active: Option<bool>,
+    pub room_type: Option<RoomType>,
+    pub host: Option<UserId>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, SimpleObject)]
 pub struct UserView {
     pub stake: Amount,
     pub active_rooms: Vec<RoomId>,
+    /// Stake backing active rooms or a relay node registration
+    pub locked: Amount,
+    /// Unstaked amount still inside the unbonding period
+    pub unbonding: Amount,
+    /// Stake that can be unstaked right now
+    pub free: Amount,
+    /// Token the stake is held in
+    pub stake_token: SupportedToken,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, SimpleObject)]
 pub struct RoomView {
+    pub room_id: RoomId,
     pub host: UserId,
+    pub co_hosts: Vec<UserId>,
+    /// Users invited to co-host who have not accepted yet
+    pub co_host_invites: Vec<UserId>,
+    /// User offered the room who has not accepted it yet
+    pub pending_host: Option<UserId>,
+    pub moderators: Vec<UserId>,
     pub room_type: RoomType,
+    /// Stake the hosts must keep while the room is active, fixed at creation
+    pub required_stake: Amount,
+    pub participants: Vec<UserId>,
+    /// Number of participants; for rooms on a dedicated chain, as last reported by that chain
+    pub participant_count: usize,
+    pub max_participants: usize,
+    /// Dedicated chain the room runs on, if any
+    pub chain_id: Option<ChainId>,
+    pub starts_at: Option<Timestamp>,
+    pub ends_at: Option<Timestamp>,
+    /// Relay nodes assigned to the room
+    pub relays: Vec<UserId>,
+    /// Whether joining requires an allowlist entry or an invite code
+    pub restricted: bool,
     pub is_active: bool,
-}
\ No newline at end of file
+    pub metadata: RoomMetadata,
+}
+
+#[derive(Debug, Serialize, Deserialize, SimpleObject)]
+pub struct NodeView {
+    pub owner: UserId,
+    pub address: NodeAddress,
+    pub region: String,
+    pub bandwidth_mbps: u64,
+    pub reputation: u64,
+    /// Time of the last heartbeat, in microseconds since the epoch
+    pub last_heartbeat: u64,
+    /// Number of rooms the node currently relays
+    pub load: usize,
+    /// Rewards accrued and not yet claimed
+    pub rewards: Amount,
+    /// Stake delegated to the node by other users
+    pub delegated_stake: Amount,
+    pub commission_percent: u8,
+    /// Whether the node's heartbeats and reputation currently qualify it to relay rooms
+    pub healthy: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, SimpleObject)]
+pub struct DelegationView {
+    pub delegator: UserId,
+    pub amount: Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, SimpleObject)]
+pub struct NodePage {
+    pub nodes: Vec<NodeView>,
+    /// Cursor to pass as `after` for the next page; `None` on the last page
+    pub next_cursor: Option<UserId>,
+}
+
+#[derive(Debug, Serialize, Deserialize, SimpleObject)]
+pub struct RoomPage {
+    pub rooms: Vec<RoomView>,
+    /// Cursor to pass as `after` for the next page; `None` on the last page
+    pub next_cursor: Option<RoomId>,
+}
+
+/// Room hosted on another chain, mirrored from its event stream
+#[derive(Debug, Serialize, Deserialize, Clone, SimpleObject)]
+pub struct FollowedRoomView {
+    pub chain_id: ChainId,
+    pub room_id: RoomId,
+    pub host: UserId,
+    pub room_type: RoomType,
+    pub participant_count: usize,
+    pub is_active: bool,
+}
+
+/// Position of a mirrored room in the followed room listing
+#[derive(Debug, Serialize, Deserialize, Clone, SimpleObject, InputObject)]
+#[graphql(input_name = "FollowedRoomCursorInput")]
+pub struct FollowedRoomCursor {
+    pub chain_id: ChainId,
+    pub room_id: RoomId,
+}
+
+#[derive(Debug, Serialize, Deserialize, SimpleObject)]
+pub struct FollowedRoomPage {
+    pub rooms: Vec<FollowedRoomView>,
+    /// Cursor to pass as `after` for the next page; `None` on the last page
+    pub next_cursor: Option<FollowedRoomCursor>,
+}
@@ -6,13 +6,22 @@ mod state;
 
 use std::sync::Arc;
 
-use chillie::{ChillieRequest, ChillieQueryResponse, UserView, RoomView};
-use linera_sdk::{linera_base_types::WithServiceAbi, views::View, Service, ServiceRuntime};
+use async_graphql::{EmptySubscription, Object, Request, Response, Schema};
+use chillie::{
+    ChillieOperation, DelegationView, FollowedRoomCursor, FollowedRoomPage, Recording, NodeFilter, NodePage, NodeView, RoomId, RoomRole, TierConfig, UserId,
+    UserView, RewardConfig, RoomFilter, RoomPage, RoomView, SlashingConfig, MAX_PAGE_SIZE,
+};
+use linera_sdk::{
+    bcs, graphql::GraphQLMutationRoot as _, linera_base_types::WithServiceAbi, views::View, Service, ServiceRuntime,
+};
 
-use self::state::{ChillieRoomState, UserState, RoomState};
+use self::state::{indices_after, ChillieRoomState, NodeState, RoomState};
+
+/// Most index entries read to serve one page of a filtered list
+const MAX_SCANNED_ROWS: usize = 10 * MAX_PAGE_SIZE;
 
 pub struct ChillieService {
-    state: ChillieRoomState,
+    state: Arc<ChillieRoomState>,
     runtime: Arc<ServiceRuntime<Self>>,
 }
 
@@ -23,39 +32,367 @@ impl WithServiceAbi for ChillieService {
 }
 
 impl Service for ChillieService {
-    type Parameters = ();
+    type Parameters = chillie::ChillieParameters;
 
     async fn new(runtime: ServiceRuntime<Self>) -> Self {
         let state = ChillieRoomState::load(runtime.root_view_storage_context())
             .await
             .expect("Failed to load state");
         ChillieService {
-            state,
+            state: Arc::new(state),
             runtime: Arc::new(runtime),
         }
     }
 
-    async fn handle_query(&self, request: ChillieRequest) -> ChillieQueryResponse {
-        match request {
-            ChillieRequest::GetUserInfo { user_id } => {
-                let user_state = self.state.users.get(&user_id).await
-                    .expect("Failed to get user state");
-                
-                ChillieQueryResponse::UserInfo(user_state.map(|u| UserView {
-                    stake: u.stake,
-                    active_rooms: u.active_rooms,
-                }))
+    async fn handle_query(&self, request: Request) -> Response {
+        let query_root = QueryRoot {
+            state: self.state.clone(),
+            runtime: self.runtime.clone(),
+        };
+        // Mutations schedule the matching operation in the next block proposed by the client
+        let schema = Schema::build(
+            query_root,
+            ChillieOperation::mutation_root(self.runtime.clone()),
+            EmptySubscription,
+        )
+        .finish();
+        schema.execute(request).await
+    }
+}
+
+struct QueryRoot {
+    state: Arc<ChillieRoomState>,
+    runtime: Arc<ServiceRuntime<ChillieService>>,
+}
+
+#[Object]
+impl QueryRoot {
+    /// Stake, locked and unbonding balances of a user
+    async fn user(&self, user_id: UserId) -> Option<UserView> {
+        let user_state = self.state.users.get(&user_id).await
+            .expect("Failed to get user state")?;
+
+        let now = self.runtime.system_time();
+        let locked = self.state.locked_stake(&user_id, &user_state, now).await
+            .expect("Failed to compute locked stake");
+        let unbonding = self.state.unbonding_stake(&user_id).await
+            .expect("Failed to get withdrawal queue");
+
+        let stake_token = self.state.stake_token_of(&user_id).await
+            .expect("Failed to get stake token");
+
+        // Expired rooms stay in the list until an operation closes them
+        let mut active_rooms = Vec::new();
+        for room_id in user_state.active_rooms {
+            let expired = self.state.rooms.get(&room_id).await
+                .expect("Failed to get room state")
+                .is_some_and(|room| room.has_expired(now));
+            if !expired {
+                active_rooms.push(room_id);
             }
-            ChillieRequest::GetRoomInfo { room_id } => {
-                let room_state = self.state.rooms.get(&room_id).await
-                    .expect("Failed to get room state");
-                
-                ChillieQueryResponse::RoomInfo(room_state.map(|r| RoomView {
-                    host: r.host,
-                    room_type: r.room_type,
-                    is_active: r.is_active,
-                }))
+        }
+
+        Some(UserView {
+            stake: user_state.stake,
+            active_rooms,
+            locked,
+            unbonding,
+            free: user_state.stake.saturating_sub(locked),
+            stake_token,
+        })
+    }
+
+    async fn room(&self, room_id: RoomId) -> Option<RoomView> {
+        let room_state = self.state.rooms.get(&room_id).await
+            .expect("Failed to get room state");
+
+        room_state.map(|room| self.room_view(room_id, room))
+    }
+
+    /// Rooms matching `filter`, starting after the room `after`
+    async fn rooms(
+        &self,
+        #[graphql(default)] filter: RoomFilter,
+        after: Option<RoomId>,
+        #[graphql(default = 20)] limit: usize,
+    ) -> RoomPage {
+        self.list_rooms(filter, after, limit).await
+    }
+
+    /// Rooms a user hosts, starting after the room `after`
+    async fn rooms_by_host(
+        &self,
+        host: UserId,
+        after: Option<RoomId>,
+        #[graphql(default = 20)] limit: usize,
+    ) -> RoomPage {
+        let filter = RoomFilter { host: Some(host), ..RoomFilter::default() };
+        self.list_rooms(filter, after, limit).await
+    }
+
+    /// Active rooms carrying a tag, starting after the room `after`
+    ///
+    /// Rooms leave the tag index when they close; `active_only` also skips rooms past their end time.
+    async fn search_rooms_by_tag(
+        &self,
+        tag: String,
+        #[graphql(default)] active_only: bool,
+        after: Option<RoomId>,
+        #[graphql(default = 20)] limit: usize,
+    ) -> RoomPage {
+        let filter = RoomFilter {
+            tag: Some(tag),
+            active: active_only.then_some(true),
+            ..RoomFilter::default()
+        };
+        self.list_rooms(filter, after, limit).await
+    }
+
+    /// Registered relay nodes matching `filter`, starting after the node `after`
+    async fn nodes(
+        &self,
+        #[graphql(default)] filter: NodeFilter,
+        after: Option<UserId>,
+        #[graphql(default = 20)] limit: usize,
+    ) -> NodePage {
+        self.list_nodes(filter, after, limit).await
+    }
+
+    async fn node(&self, node: UserId) -> Option<NodeView> {
+        let node_state = self.state.nodes.get(&node).await
+            .expect("Failed to get node")?;
+
+        Some(self.node_view(node, node_state).await)
+    }
+
+    /// Rooms a relay node is responsible for
+    async fn node_assignments(&self, node: UserId) -> Vec<RoomId> {
+        let node_state = self.state.nodes.get(&node).await
+            .expect("Failed to get node");
+
+        node_state.map(|n| n.assigned_rooms).unwrap_or_default()
+    }
+
+    /// Stake delegated to a node operator, per delegator
+    async fn delegations(&self, node: UserId) -> Vec<DelegationView> {
+        let delegations = self.state.delegations.get(&node).await
+            .expect("Failed to get delegations")
+            .unwrap_or_default();
+
+        delegations.into_iter()
+            .map(|(delegator, amount)| DelegationView { delegator, amount })
+            .collect()
+    }
+
+    async fn tier_config(&self) -> TierConfig {
+        self.state.tier_config.get().clone()
+    }
+
+    async fn reward_config(&self) -> RewardConfig {
+        *self.state.reward_config.get()
+    }
+
+    async fn slashing_config(&self) -> SlashingConfig {
+        *self.state.slashing_config.get()
+    }
+
+    /// Walrus recordings attached to a room, oldest first
+    async fn recordings(&self, room_id: RoomId) -> Vec<Recording> {
+        self.state.recordings.get(&room_id).await
+            .expect("Failed to get recordings")
+            .unwrap_or_default()
+    }
+
+    /// Rooms mirrored from followed chains, starting after the room `after`
+    async fn followed_rooms(
+        &self,
+        after: Option<FollowedRoomCursor>,
+        #[graphql(default = 20)] limit: usize,
+    ) -> FollowedRoomPage {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let after = after.map(|cursor| (cursor.chain_id, cursor.room_id));
+        let keys = indices_after(&self.state.followed_rooms, &[], after.as_ref(), limit + 1).await
+            .expect("Failed to list followed rooms");
+
+        let mut rooms = Vec::new();
+        for key in keys.iter().take(limit) {
+            if let Some(room) = self.state.followed_rooms.get(key).await
+                .expect("Failed to get followed room")
+            {
+                rooms.push(room);
+            }
+        }
+        let next_cursor = (keys.len() > limit)
+            .then(|| keys[limit - 1].clone())
+            .map(|(chain_id, room_id)| FollowedRoomCursor { chain_id, room_id });
+
+        FollowedRoomPage { rooms, next_cursor }
+    }
+}
+
+impl QueryRoot {
+    /// Returns one page of registered nodes matching the filter
+    ///
+    /// At most `MAX_SCANNED_ROWS` nodes are read per page, so a page may come back short with a
+    /// cursor to resume from.
+    async fn list_nodes(&self, filter: NodeFilter, after: Option<UserId>, limit: usize) -> NodePage {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let now = self.runtime.system_time().micros();
+
+        let owners = indices_after(&self.state.nodes, &[], after.as_ref(), MAX_SCANNED_ROWS).await
+            .expect("Failed to list nodes");
+        let scanned_all = owners.len() < MAX_SCANNED_ROWS;
+
+        let mut nodes = Vec::new();
+        let mut next_cursor = None;
+        let mut last_scanned = None;
+        for owner in owners {
+            if nodes.len() == limit {
+                next_cursor = nodes.last().map(|node: &NodeView| node.owner.clone());
+                break;
+            }
+            last_scanned = Some(owner.clone());
+
+            let Some(node) = self.state.nodes.get(&owner).await
+                .expect("Failed to get node")
+            else {
+                continue;
+            };
+
+            let fresh = filter.max_heartbeat_age_secs.map_or(true, |max_age| {
+                now.saturating_sub(node.last_heartbeat) <= max_age.saturating_mul(1_000_000)
+            });
+            let matches = fresh
+                && filter.min_reputation.map_or(true, |min| node.reputation >= min)
+                && filter.min_bandwidth_mbps.map_or(true, |min| node.bandwidth_mbps >= min)
+                && filter.region.as_ref().map_or(true, |region| node.region == *region);
+
+            if matches {
+                nodes.push(self.node_view(owner, node).await);
+            }
+        }
+        // Resume after the last node read when the scan stopped before the end
+        if next_cursor.is_none() && !scanned_all {
+            next_cursor = last_scanned;
+        }
+
+        NodePage { nodes, next_cursor }
+    }
+
+    /// Returns one page of rooms matching the filter
+    ///
+    /// Host and tag filters walk their index instead of every room. At most `MAX_SCANNED_ROWS`
+    /// rooms are read per page, so a page may come back short with a cursor to resume from.
+    async fn list_rooms(&self, filter: RoomFilter, after: Option<RoomId>, limit: usize) -> RoomPage {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let tag = filter.tag.as_ref().map(|tag| tag.trim().to_lowercase());
+
+        let room_ids = if let Some(host) = &filter.host {
+            let prefix = bcs::to_bytes(host).expect("Failed to serialize host");
+            let after = after.map(|room_id| (host.clone(), room_id));
+            indices_after(&self.state.rooms_by_host, &prefix, after.as_ref(), MAX_SCANNED_ROWS).await
+                .expect("Failed to get host index")
+                .into_iter()
+                .map(|(_, room_id)| room_id)
+                .collect()
+        } else if let Some(tag) = &tag {
+            let prefix = bcs::to_bytes(tag).expect("Failed to serialize tag");
+            let after = after.map(|room_id| (tag.clone(), room_id));
+            indices_after(&self.state.rooms_by_tag, &prefix, after.as_ref(), MAX_SCANNED_ROWS).await
+                .expect("Failed to get tag index")
+                .into_iter()
+                .map(|(_, room_id)| room_id)
+                .collect()
+        } else {
+            indices_after(&self.state.rooms, &[], after.as_ref(), MAX_SCANNED_ROWS).await
+                .expect("Failed to list rooms")
+        };
+        let scanned_all = room_ids.len() < MAX_SCANNED_ROWS;
+
+        let mut rooms = Vec::new();
+        let mut next_cursor = None;
+        let mut last_scanned = None;
+        for room_id in room_ids {
+            if rooms.len() == limit {
+                next_cursor = rooms.last().map(|room: &RoomView| room.room_id.clone());
+                break;
+            }
+            last_scanned = Some(room_id.clone());
+
+            let Some(room) = self.state.rooms.get(&room_id).await
+                .expect("Failed to get room state")
+            else {
+                continue;
+            };
+
+            let view = self.room_view(room_id, room);
+            let matches = filter.active.map_or(true, |active| view.is_active == active)
+                && filter.room_type.map_or(true, |room_type| view.room_type == room_type)
+                && filter.host.as_ref().map_or(true, |host| view.host == *host)
+                && tag.as_ref().map_or(true, |tag| view.metadata.tags.contains(tag));
+
+            if matches {
+                rooms.push(view);
             }
         }
+        // Resume after the last room read when the scan stopped before the end
+        if next_cursor.is_none() && !scanned_all {
+            next_cursor = last_scanned;
+        }
+
+        RoomPage { rooms, next_cursor }
     }
-}
\ No newline at end of file
+
+    /// Builds the public view of a room
+    fn room_view(&self, room_id: RoomId, room: RoomState) -> RoomView {
+        let chain_id = self.runtime.chain_id();
+        let now = self.runtime.system_time();
+
+        RoomView {
+            room_id,
+            restricted: room.is_restricted(),
+            participant_count: room.participant_count(chain_id),
+            max_participants: room.room_type.max_participants(),
+            co_hosts: room.members_with_role(RoomRole::CoHost),
+            moderators: room.members_with_role(RoomRole::Moderator),
+            // Expired rooms stay active in storage until an operation closes them
+            is_active: room.is_active && !room.has_expired(now),
+            host: room.host,
+            co_host_invites: room.co_host_invites,
+            pending_host: room.pending_host,
+            room_type: room.room_type,
+            required_stake: room.required_stake,
+            participants: room.participants,
+            relays: room.relays,
+            chain_id: room.chain_id,
+            starts_at: room.starts_at,
+            ends_at: room.ends_at,
+            metadata: room.metadata,
+        }
+    }
+
+    /// Builds the public view of a registered node
+    async fn node_view(&self, owner: UserId, node: NodeState) -> NodeView {
+        let rewards = self.state.rewards.get(&owner).await
+            .expect("Failed to get rewards")
+            .unwrap_or_default();
+        let delegated_stake = self.state.delegated_stake(&owner).await
+            .expect("Failed to get delegations");
+        let now = self.runtime.system_time().micros();
+        let healthy = node.is_healthy(now, self.state.slashing_config.get());
+
+        NodeView {
+            load: node.assigned_rooms.len(),
+            owner,
+            address: node.address,
+            region: node.region,
+            bandwidth_mbps: node.bandwidth_mbps,
+            reputation: node.reputation,
+            last_heartbeat: node.last_heartbeat,
+            rewards,
+            delegated_stake,
+            commission_percent: node.commission_percent,
+            healthy,
+        }
+    }
+}
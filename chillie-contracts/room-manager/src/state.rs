@@ -1,11 +1,16 @@
 // Chillie - Room Manager State
 
-use chillie::{RoomId, UserId, RoomType, SupportedToken};
+use chillie::{
+    FollowedRoomView, Recording, RewardConfig, RoomMetadata, RoomId, UserId, RoomRole, RoomType, SlashingConfig, StakingTier,
+    SupportedToken, TierConfig,
+};
 use linera_sdk::{
-    linera_base_types::Amount,
-    views::{linera_views, MapView, RegisterView, RootView, ViewStorageContext},
+    bcs,
+    linera_base_types::{Amount, ChainId, Timestamp},
+    views::{linera_views, LogView, MapView, RegisterView, RootView, ViewError, ViewStorageContext},
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserState {
@@ -16,16 +21,238 @@ pub struct UserState {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RoomState {
     pub host: UserId,
+    /// Staking tier of the host when they took the room over; sets the platform fee on its payments
+    pub host_tier: StakingTier,
     pub room_type: RoomType,
+    /// Stake required from the room's backers, fixed when the room was created
+    pub required_stake: Amount,
     pub participants: Vec<UserId>,
     pub is_active: bool,
+    /// Users admitted without an invite code; `None` when no allowlist is set
+    pub allowlist: Option<Vec<UserId>>,
+    /// Shared code that admits anyone who presents it
+    pub invite_code: Option<String>,
+    /// Users kicked from the room
+    pub banned: Vec<UserId>,
+    /// Co-hosts and moderators; the host is tracked in `host`
+    pub roles: BTreeMap<UserId, RoomRole>,
+    /// Users offered the co-host role who have not accepted it yet
+    pub co_host_invites: Vec<UserId>,
+    /// User offered the host role who has not accepted it yet
+    pub pending_host: Option<UserId>,
+    /// Relay nodes assigned to the room
+    pub relays: Vec<UserId>,
+    /// Relay nodes assigned when the room closed, which may still attach its recording
+    pub closing_relays: Vec<UserId>,
+    /// Dedicated chain handling the room's participants and payments, if any
+    pub chain_id: Option<ChainId>,
+    /// Participants last reported by the dedicated chain; only kept on the main chain's entry
+    pub participant_count: usize,
+    /// Time from which participants may join
+    pub starts_at: Option<Timestamp>,
+    /// Time after which the room is closed
+    pub ends_at: Option<Timestamp>,
+    pub metadata: RoomMetadata,
+}
+
+impl RoomState {
+    /// Returns the role a user holds in this room
+    pub fn role_of(&self, user_id: &UserId) -> Option<RoomRole> {
+        if self.host == *user_id {
+            Some(RoomRole::Host)
+        } else {
+            self.roles.get(user_id).copied()
+        }
+    }
+
+    /// Whether a user may close the room or change its settings
+    pub fn can_manage(&self, user_id: &UserId) -> bool {
+        matches!(self.role_of(user_id), Some(RoomRole::Host | RoomRole::CoHost))
+    }
+
+    /// Whether a user may remove participants
+    pub fn can_moderate(&self, user_id: &UserId) -> bool {
+        self.role_of(user_id).is_some()
+    }
+
+    /// Returns the users holding the given non-host role
+    pub fn members_with_role(&self, role: RoomRole) -> Vec<UserId> {
+        self.roles.iter()
+            .filter(|(_, r)| **r == role)
+            .map(|(user_id, _)| user_id.clone())
+            .collect()
+    }
+
+    /// Returns the host followed by the co-hosts, whose stakes together back the room's tier
+    pub fn backers(&self) -> Vec<UserId> {
+        let mut backers = vec![self.host.clone()];
+        backers.extend(self.members_with_role(RoomRole::CoHost));
+        backers
+    }
+
+    /// Whether the room's scheduled start has been reached
+    pub fn has_started(&self, now: Timestamp) -> bool {
+        self.starts_at.is_none_or(|starts_at| now >= starts_at)
+    }
+
+    /// Whether the room's scheduled end has passed
+    pub fn has_expired(&self, now: Timestamp) -> bool {
+        self.ends_at.is_some_and(|ends_at| now >= ends_at)
+    }
+
+    /// Whether the room runs on a dedicated chain other than `current`
+    pub fn is_hosted_elsewhere(&self, current: ChainId) -> bool {
+        self.chain_id.is_some_and(|chain_id| chain_id != current)
+    }
+
+    /// Whether `current` is the room's dedicated chain
+    pub fn is_room_chain(&self, current: ChainId) -> bool {
+        self.chain_id == Some(current)
+    }
+
+    /// Returns the number of participants, as reported by the room chain for rooms hosted elsewhere
+    pub fn participant_count(&self, current: ChainId) -> usize {
+        if self.is_hosted_elsewhere(current) {
+            self.participant_count
+        } else {
+            self.participants.len()
+        }
+    }
+
+    /// Whether the room restricts who may join
+    pub fn is_restricted(&self) -> bool {
+        self.allowlist.is_some() || self.invite_code.is_some()
+    }
+
+    /// Checks the room's admission rules for a user presenting an optional invite code
+    pub fn admits(&self, user_id: &UserId, invite_code: Option<&str>) -> bool {
+        if self.host == *user_id {
+            return true;
+        }
+        if self.banned.contains(user_id) {
+            return false;
+        }
+        if !self.is_restricted() {
+            return true;
+        }
+
+        let allowlisted = self.allowlist.as_ref()
+            .is_some_and(|allowlist| allowlist.contains(user_id));
+        let code_matches = match (&self.invite_code, invite_code) {
+            (Some(expected), Some(given)) => expected == given,
+            _ => false,
+        };
+
+        allowlisted || code_matches
+    }
+}
+
+/// Stake that has been unstaked but is still inside the unbonding period
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PendingWithdrawal {
+    pub amount: Amount,
+    pub release_at: Timestamp,
+    /// Node the stake was undelegated from, which can still slash it; `None` for unstaked stake
+    pub node: Option<UserId>,
+}
+
+/// Stake of an unregistered node's operator that stays locked until `release_at`
+///
+/// Until then the node can still be reported and slashed for what it did while registered.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeExit {
+    pub locked: Amount,
+    pub release_at: Timestamp,
+    /// Reputation when the node left, lowered by later reports
+    pub reputation: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NodeState {
     pub address: String,
+    pub region: String,
+    pub bandwidth_mbps: u64,
+    /// Stake required to relay rooms, fixed at registration
+    pub required_stake: Amount,
+    /// Part of `required_stake` the operator must back themselves, fixed at registration
+    pub required_self_stake: Amount,
     pub last_heartbeat: u64,
     pub reputation: u64,
+    /// Heartbeat intervals since `last_heartbeat` that were already penalized
+    pub missed_heartbeats: u64,
+    /// Rooms this node relays
+    pub assigned_rooms: Vec<RoomId>,
+    /// Share of the node's rewards kept by the operator before delegators are paid, in percent
+    pub commission_percent: u8,
+}
+
+/// Number of heartbeat intervals a node may be silent before it stops receiving rooms
+pub const HEALTHY_HEARTBEAT_INTERVALS: u64 = 3;
+
+impl NodeState {
+    /// Whether the node is live and trusted enough to relay rooms
+    pub fn is_healthy(&self, now: u64, config: &SlashingConfig) -> bool {
+        let max_silence = config.heartbeat_interval_secs
+            .saturating_mul(HEALTHY_HEARTBEAT_INTERVALS)
+            .saturating_mul(1_000_000);
+        self.reputation >= config.slash_threshold
+            && now.saturating_sub(self.last_heartbeat) <= max_silence
+    }
+
+    /// Part of the requirement the operator backs with their own stake, given the stake delegated to the node
+    pub fn own_backing(&self, delegated: Amount) -> Amount {
+        self.required_stake.saturating_sub(delegated).max(self.required_self_stake)
+    }
+
+    /// Ranking used to balance rooms: higher reputation and lower load score higher
+    pub fn assignment_score(&self) -> u64 {
+        self.reputation.saturating_mul(1000) / (self.assigned_rooms.len() as u64 + 1)
+    }
+}
+
+/// Misbehaviour report filed by a registered node against another node
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeReport {
+    pub reporter: UserId,
+    pub evidence: String,
+    pub timestamp: u64,
+}
+
+/// Collects up to `max` indices of `map` whose serialization starts with `prefix`, after `after`
+///
+/// Indices are visited in storage order, which follows their serialization, so a cursor that
+/// was removed in the meantime still resumes at the right place. Values are not loaded.
+pub async fn indices_after<I, V>(
+    map: &MapView<ViewStorageContext, I, V>,
+    prefix: &[u8],
+    after: Option<&I>,
+    max: usize,
+) -> Result<Vec<I>, ViewError>
+where
+    I: Serialize + DeserializeOwned + Clone + Send + Sync,
+    V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    let after = after.map(bcs::to_bytes).transpose()?;
+    let mut indices = Vec::new();
+    if max == 0 {
+        return Ok(indices);
+    }
+
+    map.for_each_index_while(|index| {
+        let key = bcs::to_bytes(&index)?;
+        if !key.starts_with(prefix) {
+            // Indices sharing a prefix are stored next to each other
+            return Ok(key.as_slice() < prefix);
+        }
+        if after.as_ref().is_some_and(|after| key <= *after) {
+            return Ok(true);
+        }
+        indices.push(index);
+        Ok(indices.len() < max)
+    })
+    .await?;
+
+    Ok(indices)
 }
 
 /// The application state for the Chillie Room Manager
@@ -38,6 +265,187 @@ pub struct ChillieRoomState {
     pub rooms: MapView<RoomId, RoomState>,
     /// Map of registered relay nodes
     pub nodes: MapView<UserId, NodeState>,
+    /// Stake delegated to each relay node operator, per delegator
+    pub delegations: MapView<UserId, BTreeMap<UserId, Amount>>,
     /// Configured staking token
     pub staking_token: RegisterView<SupportedToken>,
+    /// Withdrawal queue of unbonding stake per user
+    pub unbonding: MapView<UserId, Vec<PendingWithdrawal>>,
+    /// Stake of unregistered node operators that stays locked until their unbonding period ends
+    pub node_exits: MapView<UserId, NodeExit>,
+    /// Delegators of each node whose undelegated stake may still be unbonding
+    pub unbonding_delegators: MapView<UserId, BTreeSet<UserId>>,
+    /// Open misbehaviour reports per node
+    pub reports: MapView<UserId, Vec<NodeReport>>,
+    /// Reputation and slashing parameters
+    pub slashing_config: RegisterView<SlashingConfig>,
+    /// Slashed stake held by the protocol, per token
+    pub treasury: MapView<SupportedToken, Amount>,
+    /// Relay reward emission parameters
+    pub reward_config: RegisterView<RewardConfig>,
+    /// Funds not yet distributed to relay nodes
+    pub reward_pool: RegisterView<Amount>,
+    /// Last epoch whose emission was distributed
+    pub last_reward_epoch: RegisterView<u64>,
+    /// Distributed but unclaimed rewards per owner
+    pub rewards: MapView<UserId, Amount>,
+    /// Sum of all unclaimed rewards
+    pub unclaimed_rewards: RegisterView<Amount>,
+    /// Staking thresholds applied to new rooms and node registrations
+    pub tier_config: RegisterView<TierConfig>,
+    /// Owner allowed to change the configuration
+    pub admin: RegisterView<Option<UserId>>,
+    /// Tokens of stakes made before the last staking token switch, for holders brought up to date
+    pub legacy_stake_tokens: MapView<UserId, SupportedToken>,
+    /// Token replaced by each staking token switch, oldest first
+    pub replaced_staking_tokens: LogView<SupportedToken>,
+    /// Number of staking token switches each holder was last brought up to date with
+    pub stake_token_switches_seen: MapView<UserId, u64>,
+    /// Whether new rooms can be created
+    pub room_creation_paused: RegisterView<bool>,
+    /// Rooms mirrored from the event streams of followed chains
+    pub followed_rooms: MapView<(ChainId, RoomId), FollowedRoomView>,
+    /// Active rooms carrying each tag
+    pub rooms_by_tag: MapView<(String, RoomId), ()>,
+    /// Rooms each user hosts, including closed ones
+    pub rooms_by_host: MapView<(UserId, RoomId), ()>,
+    /// Walrus recordings attached to each room
+    pub recordings: MapView<RoomId, Vec<Recording>>,
+}
+
+impl ChillieRoomState {
+    /// Returns the current stake of a user, or zero if they never staked
+    pub async fn stake_of(&self, user_id: &UserId) -> Result<Amount, ViewError> {
+        Ok(self.users.get(user_id).await?.map_or(Amount::ZERO, |user| user.stake))
+    }
+
+    /// Returns the stake of a user held in the current staking token
+    ///
+    /// Stake still held in a replaced token does not count toward tiers or backing requirements.
+    pub async fn current_stake_of(&self, user_id: &UserId) -> Result<Amount, ViewError> {
+        if self.stake_token_of(user_id).await? != *self.staking_token.get() {
+            return Ok(Amount::ZERO);
+        }
+        self.stake_of(user_id).await
+    }
+
+    /// Returns the combined current-token stake of the host and co-hosts backing a room
+    pub async fn backing_stake(&self, room: &RoomState) -> Result<Amount, ViewError> {
+        let mut total = Amount::ZERO;
+        for backer in room.backers() {
+            total = total.saturating_add(self.current_stake_of(&backer).await?);
+        }
+        Ok(total)
+    }
+
+    /// Returns the part of a user's stake that backs active rooms or a relay node registration
+    ///
+    /// For rooms with co-hosts, only the part of the requirement not covered by the other
+    /// backers is locked, and rooms past their end time lock nothing even before they are closed.
+    /// Operators of recently unregistered nodes keep their node stake locked until `now` passes
+    /// the end of the unbonding period.
+    pub async fn locked_stake(
+        &self,
+        user_id: &UserId,
+        user_state: &UserState,
+        now: Timestamp,
+    ) -> Result<Amount, ViewError> {
+        let mut locked = Amount::ZERO;
+
+        for room_id in &user_state.active_rooms {
+            if let Some(room) = self.rooms.get(room_id).await? {
+                if room.has_expired(now) {
+                    continue;
+                }
+                let own = self.current_stake_of(user_id).await?;
+                let others = self.backing_stake(&room).await?.saturating_sub(own);
+                locked = locked.max(room.required_stake.saturating_sub(others));
+            }
+        }
+
+        if let Some(node) = self.nodes.get(user_id).await? {
+            // Delegations cover part of the node's requirement
+            let delegated = self.delegated_stake(user_id).await?;
+            locked = locked.max(node.own_backing(delegated));
+        }
+
+        if let Some(exit) = self.node_exits.get(user_id).await? {
+            if exit.release_at > now {
+                locked = locked.max(exit.locked);
+            }
+        }
+
+        Ok(locked)
+    }
+
+    /// Returns the total stake delegated to a node operator
+    pub async fn delegated_stake(&self, node: &UserId) -> Result<Amount, ViewError> {
+        let delegations = self.delegations.get(node).await?.unwrap_or_default();
+        Ok(delegations.values().fold(Amount::ZERO, |total, amount| total.saturating_add(*amount)))
+    }
+
+    /// Whether the operator's stake and the delegations together meet a node's requirement
+    pub async fn is_fully_backed(&self, owner: &UserId, node: &NodeState) -> Result<bool, ViewError> {
+        let backing = self.current_stake_of(owner).await?.saturating_add(self.delegated_stake(owner).await?);
+        Ok(backing >= node.required_stake)
+    }
+
+    /// Returns the token a user's stake and queued withdrawals are held in
+    ///
+    /// Holders not brought up to date since a staking token switch still hold the token that was
+    /// current when they last were.
+    pub async fn stake_token_of(&self, user_id: &UserId) -> Result<SupportedToken, ViewError> {
+        if let Some(token) = self.legacy_stake_tokens.get(user_id).await? {
+            return Ok(token);
+        }
+
+        let seen = self.stake_token_switches_seen.get(user_id).await?.unwrap_or_default() as usize;
+        if seen < self.replaced_staking_tokens.count() && self.holds_stake(user_id).await? {
+            if let Some(token) = self.replaced_staking_tokens.get(seen).await? {
+                return Ok(token);
+            }
+        }
+        Ok(*self.staking_token.get())
+    }
+
+    /// Whether a user still has stake or queued withdrawals in a replaced staking token
+    pub async fn holds_legacy_stake(&self, user_id: &UserId) -> Result<bool, ViewError> {
+        Ok(self.stake_token_of(user_id).await? != *self.staking_token.get() && self.holds_stake(user_id).await?)
+    }
+
+    /// Brings a holder up to date with the staking token switches, pinning stake still held in a
+    /// replaced token
+    ///
+    /// Switches do not visit every holder; each one is migrated when it next changes its stake.
+    pub async fn migrate_stake_token(&mut self, user_id: &UserId) -> Result<(), ViewError> {
+        if self.holds_legacy_stake(user_id).await? {
+            let token = self.stake_token_of(user_id).await?;
+            self.legacy_stake_tokens.insert(user_id, token)?;
+        } else {
+            self.legacy_stake_tokens.remove(user_id)?;
+        }
+        let switches = self.replaced_staking_tokens.count() as u64;
+        self.stake_token_switches_seen.insert(user_id, switches)
+    }
+
+    /// Whether a user has stake or queued withdrawals
+    async fn holds_stake(&self, user_id: &UserId) -> Result<bool, ViewError> {
+        Ok(self.stake_of(user_id).await? != Amount::ZERO || self.unbonding_stake(user_id).await? != Amount::ZERO)
+    }
+
+    /// Records `host` as the host of a room in the host index
+    pub fn index_host(&mut self, room_id: &RoomId, host: &UserId) -> Result<(), ViewError> {
+        self.rooms_by_host.insert(&(host.clone(), room_id.clone()), ())
+    }
+
+    /// Removes a room from a former host's entries in the host index
+    pub fn unindex_host(&mut self, room_id: &RoomId, host: &UserId) -> Result<(), ViewError> {
+        self.rooms_by_host.remove(&(host.clone(), room_id.clone()))
+    }
+
+    /// Returns the total amount still waiting in a user's withdrawal queue
+    pub async fn unbonding_stake(&self, user_id: &UserId) -> Result<Amount, ViewError> {
+        let queue = self.unbonding.get(user_id).await?.unwrap_or_default();
+        Ok(queue.iter().fold(Amount::ZERO, |total, entry| total.saturating_add(entry.amount)))
+    }
 }
\ No newline at end of file
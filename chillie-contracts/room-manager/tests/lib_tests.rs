@@ -0,0 +1,75 @@
+use chillie::{is_valid_node_address, RoomMetadata, StakingTier, TierConfig, MAX_TAGS};
+use linera_sdk::linera_base_types::Amount;
+
+fn metadata_with_tags(tags: &[&str]) -> RoomMetadata {
+    RoomMetadata {
+        title: "Weekly sync".to_string(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        ..RoomMetadata::default()
+    }
+}
+
+#[test]
+fn test_tier_for_stake_picks_the_highest_tier_reached() {
+    let config = TierConfig::default();
+
+    assert_eq!(config.tier_for_stake(Amount::ZERO), StakingTier::Basic);
+    assert_eq!(config.tier_for_stake(Amount::from_tokens(99)), StakingTier::Basic);
+    assert_eq!(config.tier_for_stake(Amount::from_tokens(100)), StakingTier::Podcast);
+    assert_eq!(config.tier_for_stake(Amount::from_tokens(500)), StakingTier::SoloStream);
+    assert_eq!(config.tier_for_stake(Amount::from_tokens(4999)), StakingTier::MultiStream);
+    assert_eq!(config.tier_for_stake(Amount::from_tokens(5000)), StakingTier::RelayNode);
+}
+
+#[test]
+fn test_tier_for_stake_follows_the_configured_thresholds() {
+    let config = TierConfig { podcast: Amount::from_tokens(10), ..TierConfig::default() };
+
+    assert_eq!(config.tier_for_stake(Amount::from_tokens(10)), StakingTier::Podcast);
+    assert_eq!(config.tier_for_stake(Amount::from_tokens(9)), StakingTier::Basic);
+}
+
+#[test]
+fn test_normalize_trims_lowercases_and_deduplicates() {
+    let metadata = RoomMetadata {
+        title: "  Weekly sync ".to_string(),
+        description: " Agenda\n".to_string(),
+        tags: vec![" Rust".to_string(), "rust".to_string(), "web3".to_string()],
+        language: Some("pt-BR".to_string()),
+        thumbnail_blob: None,
+    };
+
+    let metadata = metadata.normalize().expect("Metadata should be valid");
+    assert_eq!(metadata.title, "Weekly sync");
+    assert_eq!(metadata.description, "Agenda");
+    assert_eq!(metadata.tags, vec!["rust".to_string(), "web3".to_string()]);
+}
+
+#[test]
+fn test_normalize_rejects_invalid_fields() {
+    assert!(metadata_with_tags(&["two words"]).normalize().is_err());
+    assert!(metadata_with_tags(&[""]).normalize().is_err());
+
+    let too_many: Vec<String> = (0..=MAX_TAGS).map(|index| format!("tag-{}", index)).collect();
+    let too_many: Vec<&str> = too_many.iter().map(String::as_str).collect();
+    assert!(metadata_with_tags(&too_many).normalize().is_err());
+
+    let bad_language = RoomMetadata { language: Some("english".to_string()), ..metadata_with_tags(&[]) };
+    assert!(bad_language.normalize().is_err());
+
+    let bad_thumbnail = RoomMetadata { thumbnail_blob: Some("not a blob".to_string()), ..metadata_with_tags(&[]) };
+    assert!(bad_thumbnail.normalize().is_err());
+}
+
+#[test]
+fn test_node_addresses_need_a_host_and_port() {
+    assert!(is_valid_node_address("127.0.0.1:8080"));
+    assert!(is_valid_node_address("[::1]:443"));
+    assert!(is_valid_node_address("relay-1.example.com:443"));
+
+    assert!(!is_valid_node_address("relay.example.com"));
+    assert!(!is_valid_node_address("relay.example.com:0"));
+    assert!(!is_valid_node_address(":8080"));
+    assert!(!is_valid_node_address("relay_1.example.com:443"));
+    assert!(!is_valid_node_address("-relay.example.com:443"));
+}
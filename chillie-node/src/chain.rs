@@ -0,0 +1,261 @@
+use anyhow::Result;
+use chillie::{is_valid_blob_id, is_valid_node_address, ChillieError, NodeView, RoomId};
+use linera_sdk::linera_base_types::Amount;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const DEFAULT_SERVICE_URL: &str = "http://localhost:8080";
+
+/// Client for the Chillie room-manager application exposed by a Linera node service
+pub struct ChillieClient {
+    client: reqwest::Client,
+    chain_id: String,
+    application_url: String,
+}
+
+#[derive(Deserialize)]
+struct GraphQLResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQLError>,
+}
+
+#[derive(Deserialize)]
+struct GraphQLError {
+    message: String,
+}
+
+impl ChillieClient {
+    pub fn new(service_url: Option<&str>, chain_id: &str, application_id: &str) -> Self {
+        let service_url = service_url.unwrap_or(DEFAULT_SERVICE_URL).trim_end_matches('/');
+        Self {
+            client: reqwest::Client::new(),
+            chain_id: chain_id.to_string(),
+            application_url: format!("{}/chains/{}/applications/{}", service_url, chain_id, application_id),
+        }
+    }
+
+    /// Sends a GraphQL query to the room-manager service and returns its data
+    pub async fn query<T: DeserializeOwned>(&self, query: &str, variables: Value) -> Result<T> {
+        let response = self.client
+            .post(&self.application_url)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Query failed: {}", response.status()));
+        }
+
+        let response: GraphQLResponse<T> = response.json().await?;
+        if let Some(error) = response.errors.into_iter().next() {
+            return Err(anyhow::anyhow!("Query failed: {}", error.message));
+        }
+        response.data.ok_or_else(|| anyhow::anyhow!("Query returned no data"))
+    }
+
+    /// Checks that a room exists on this chain and accepts recordings at the given replay price
+    ///
+    /// Operations scheduled through the node service do not report the contract's response, so
+    /// the rejections that can be predicted are raised here as a `ChillieError`.
+    pub async fn check_recording_target(&self, room_id: &str, replay_price: Option<Amount>) -> Result<()> {
+        #[derive(Deserialize)]
+        struct Room {
+            chain_id: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Data {
+            room: Option<Room>,
+        }
+
+        if replay_price == Some(Amount::ZERO) {
+            return Err(ChillieError::InvalidReplayPrice.into());
+        }
+
+        let data: Data = self.query(
+            "query($roomId: String!) { room(roomId: $roomId) { chain_id: chainId } }",
+            json!({ "roomId": room_id }),
+        ).await?;
+        let room = data.room.ok_or(ChillieError::RoomNotFound)?;
+        if let Some(chain_id) = room.chain_id.filter(|chain_id| *chain_id != self.chain_id) {
+            return Err(ChillieError::RoomHostedElsewhere { chain_id: chain_id.parse()? }.into());
+        }
+        Ok(())
+    }
+
+    /// Schedules an `AttachRecording` operation on the service's chain, signed by its wallet
+    ///
+    /// The host or relay check is left to the contract, since the signer is only known to the wallet;
+    /// a recording missing after the block is committed was rejected by it.
+    pub async fn attach_recording(
+        &self,
+        room_id: &str,
+        blob_id: &str,
+        duration_secs: u64,
+        size_bytes: u64,
+        replay_price: Option<Amount>,
+    ) -> Result<()> {
+        #[derive(Deserialize)]
+        struct Recording {
+            blob_id: String,
+        }
+        #[derive(Deserialize)]
+        struct Data {
+            recordings: Vec<Recording>,
+        }
+
+        if !is_valid_blob_id(blob_id) {
+            return Err(ChillieError::InvalidBlobId.into());
+        }
+        self.check_recording_target(room_id, replay_price).await?;
+        let recordings_query = "query($roomId: String!) { recordings(roomId: $roomId) { blob_id: blobId } }";
+        let data: Data = self.query(recordings_query, json!({ "roomId": room_id })).await?;
+        if data.recordings.iter().any(|recording| recording.blob_id == blob_id) {
+            return Err(ChillieError::RecordingAlreadyAttached.into());
+        }
+
+        let _: Value = self.query(
+            "mutation($roomId: String!, $blobId: String!, $durationSecs: Int!, $sizeBytes: Int!, $replayPrice: Amount) {
+                attachRecording(
+                    roomId: $roomId,
+                    blobId: $blobId,
+                    durationSecs: $durationSecs,
+                    sizeBytes: $sizeBytes,
+                    replayPrice: $replayPrice
+                )
+            }",
+            json!({
+                "roomId": room_id,
+                "blobId": blob_id,
+                "durationSecs": duration_secs,
+                "sizeBytes": size_bytes,
+                "replayPrice": replay_price,
+            }),
+        ).await?;
+
+        let data: Data = self.query(recordings_query, json!({ "roomId": room_id })).await?;
+        if !data.recordings.iter().any(|recording| recording.blob_id == blob_id) {
+            return Err(ChillieError::NotHostOrRelay.into());
+        }
+        Ok(())
+    }
+
+    /// Schedules a `RegisterNode` operation signed by the service's wallet, which must own `node`
+    ///
+    /// The stake requirement is left to the contract; a node missing after the block is committed
+    /// was rejected by it.
+    pub async fn register_node(
+        &self,
+        node: &str,
+        address: &str,
+        region: &str,
+        bandwidth_mbps: u64,
+        commission_percent: u8,
+    ) -> Result<NodeView> {
+        if region.is_empty() || region.len() > 64 {
+            return Err(ChillieError::InvalidRegion.into());
+        }
+        if commission_percent > 100 {
+            return Err(ChillieError::InvalidCommission.into());
+        }
+        if !is_valid_node_address(address) {
+            return Err(ChillieError::InvalidNodeAddress.into());
+        }
+        if self.node_info(node).await?.is_some() {
+            return Err(ChillieError::NodeAlreadyRegistered.into());
+        }
+
+        let _: Value = self.query(
+            "mutation($address: String!, $region: String!, $bandwidthMbps: Int!, $commissionPercent: Int!) {
+                registerNode(
+                    address: $address,
+                    region: $region,
+                    bandwidthMbps: $bandwidthMbps,
+                    commissionPercent: $commissionPercent
+                )
+            }",
+            json!({
+                "address": address,
+                "region": region,
+                "bandwidthMbps": bandwidth_mbps,
+                "commissionPercent": commission_percent,
+            }),
+        ).await?;
+
+        self.node_info(node).await?.ok_or_else(|| {
+            anyhow::anyhow!("Registration rejected: {} does not stake enough to operate a relay node", node)
+        })
+    }
+
+    /// Schedules a `Heartbeat` operation signed by the service's wallet, which must own `node`
+    ///
+    /// A node that missed too many heartbeats is slashed instead of revived, so a node missing
+    /// after the block is committed was slashed.
+    pub async fn heartbeat(&self, node: &str) -> Result<NodeView> {
+        if self.node_info(node).await?.is_none() {
+            return Err(ChillieError::NodeNotRegistered.into());
+        }
+
+        let _: Value = self.query("mutation { heartbeat }", json!({})).await?;
+
+        self.node_info(node).await?.ok_or_else(|| {
+            anyhow::anyhow!("Heartbeat rejected: {} was slashed and is no longer registered", node)
+        })
+    }
+
+    /// Returns the time the contract expects between two heartbeats of a node
+    pub async fn heartbeat_interval(&self) -> Result<Duration> {
+        #[derive(Deserialize)]
+        struct SlashingConfig {
+            heartbeat_interval_secs: u64,
+        }
+        #[derive(Deserialize)]
+        struct Data {
+            slashing_config: SlashingConfig,
+        }
+
+        let data: Data = self.query(
+            "query { slashing_config: slashingConfig { heartbeat_interval_secs: heartbeatIntervalSecs } }",
+            json!({}),
+        ).await?;
+        Ok(Duration::from_secs(data.slashing_config.heartbeat_interval_secs))
+    }
+
+    /// Returns the rooms a relay node is responsible for
+    pub async fn node_assignments(&self, node: &str) -> Result<Vec<RoomId>> {
+        #[derive(Deserialize)]
+        struct Data {
+            node_assignments: Vec<RoomId>,
+        }
+
+        let data: Data = self.query(
+            "query($node: String!) { node_assignments: nodeAssignments(node: $node) }",
+            json!({ "node": node }),
+        ).await?;
+        Ok(data.node_assignments)
+    }
+
+    /// Returns the registration, reputation and accrued rewards of a relay node
+    pub async fn node_info(&self, node: &str) -> Result<Option<NodeView>> {
+        #[derive(Deserialize)]
+        struct Data {
+            node: Option<NodeView>,
+        }
+
+        // Aliases map the GraphQL field names onto the serde names of `NodeView`
+        let data: Data = self.query(
+            "query($node: String!) {
+                node(node: $node) {
+                    owner address region reputation load rewards healthy
+                    bandwidth_mbps: bandwidthMbps
+                    last_heartbeat: lastHeartbeat
+                    delegated_stake: delegatedStake
+                    commission_percent: commissionPercent
+                }
+            }",
+            json!({ "node": node }),
+        ).await?;
+        Ok(data.node)
+    }
+}
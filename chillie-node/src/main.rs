@@ -1,10 +1,15 @@
+mod chain;
 mod walrus;
 mod shelby;
 mod signaling;
 
+use chillie::ChillieError;
 use clap::{Parser, Subcommand};
+use linera_sdk::linera_base_types::Amount;
 use std::net::SocketAddr;
 use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Parser)]
 #[command(name = "chillie-node")]
@@ -12,25 +17,64 @@ use std::path::Path;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// URL of the Linera node service
+    #[arg(long, global = true)]
+    service_url: Option<String>,
+
+    /// Chain hosting the Chillie room-manager application
+    #[arg(long, global = true)]
+    chain_id: Option<String>,
+
+    /// Application ID of the Chillie room-manager
+    #[arg(long, global = true)]
+    app_id: Option<String>,
+}
+
+impl Cli {
+    /// Builds a room-manager client from the global connection options
+    fn chain_client(&self) -> anyhow::Result<chain::ChillieClient> {
+        let chain_id = self.chain_id.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("--chain-id is required for this command"))?;
+        let app_id = self.app_id.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("--app-id is required for this command"))?;
+        Ok(chain::ChillieClient::new(self.service_url.as_deref(), chain_id, app_id))
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Register this node with the Chillie contract
     Register {
+        /// Owner the node service's wallet signs for
+        #[arg(short, long)]
+        node: String,
+
         /// The address to advertise (e.g., 127.0.0.1:8080)
         #[arg(short, long)]
         address: String,
-        
-        /// Path to the wallet/keyfile (placeholder)
-        #[arg(short, long)]
-        keyfile: Option<String>,
+
+        /// Region code advertised for relay discovery (e.g., us-east, eu-central)
+        #[arg(short, long, default_value = "global")]
+        region: String,
+
+        /// Advertised relay bandwidth in Mbps
+        #[arg(short, long, default_value = "1000")]
+        bandwidth_mbps: u64,
+
+        /// Share of relay rewards kept before paying delegators, in percent
+        #[arg(long, default_value = "10")]
+        commission_percent: u8,
     },
     /// Start the relay service (Standard)
     Start {
         /// Port to listen on
         #[arg(short, long, default_value = "8080")]
         port: u16,
+
+        /// Registered node to keep alive with periodic heartbeats
+        #[arg(short, long)]
+        node: Option<String>,
     },
     /// Start the relay service in Shelby Mode (High Performance)
     StartShelby {
@@ -41,70 +85,180 @@ enum Commands {
         #[arg(short, long, default_value = "global")]
         region: String,
     },
+    /// Send a single heartbeat for a registered node
+    Heartbeat {
+        /// Owner of the node as registered with the contract
+        #[arg(short, long)]
+        node: String,
+    },
     /// Check node status
-    Status,
+    Status {
+        /// Owner of the node as registered with the contract
+        #[arg(short, long)]
+        node: String,
+    },
+    /// List the rooms this node is assigned to relay
+    Assignments {
+        /// Owner of the node as registered with the contract
+        #[arg(short, long)]
+        node: String,
+    },
     /// Upload a recording to Walrus (Cold Storage)
     Upload {
         /// Path to the file to upload
         #[arg(short, long)]
         file: String,
+
+        /// Room to attach the recording to after the upload
+        #[arg(long)]
+        room: Option<String>,
+
+        /// Length of the recording in seconds
+        #[arg(long, default_value = "0", requires = "room")]
+        duration_secs: u64,
+
+        /// Price of replay access in tokens (e.g. `0.5`); replays are free when unset
+        #[arg(long, requires = "room")]
+        replay_price: Option<Amount>,
     },
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
 
+    match run(&cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report_error(&e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: &Cli) -> anyhow::Result<()> {
     match &cli.command {
-        Commands::Register { address, keyfile } => {
+        Commands::Register { node, address, region, bandwidth_mbps, commission_percent } => {
             println!("Registering node at address: {}", address);
-            println!("Keyfile: {:?}", keyfile);
-            // TODO: Implement contract call
-            println!("Node registered successfully! (Mock)");
+            println!("Region: {}, bandwidth: {} Mbps", region, bandwidth_mbps);
+            println!("Commission: {}%", commission_percent);
+            let info = cli.chain_client()?
+                .register_node(node, address, region, *bandwidth_mbps, *commission_percent)
+                .await?;
+            println!("Node {} registered successfully!", info.owner);
         }
-        Commands::Start { port } => {
+        Commands::Start { port, node } => {
+            // Registered nodes that stop sending heartbeats lose reputation and are eventually slashed
+            if let Some(node) = node {
+                let client = cli.chain_client()?;
+                let interval = client.heartbeat_interval().await?;
+                println!("Sending heartbeats for {}", node);
+                tokio::spawn(send_heartbeats(client, node.clone(), interval));
+            }
             signaling::start_signaling_server(*port).await;
         }
         Commands::StartShelby { aptos_address, region } => {
             println!("Initializing Shelby High-Performance Relay...");
             let client = shelby::ShelbyClient::new(aptos_address.clone(), region.clone());
-            
-            match client.connect().await {
-                Ok(status) => {
-                    println!("✅ Connected to Shelby Mesh!");
-                    println!("Latency: {}ms", status.latency_ms);
-                    println!("Aptos Address: {}", aptos_address);
-                    println!("Region: {}", region);
-                    println!("Waiting for high-bandwidth streams...");
-                    
-                    // Keep alive
-                    loop {
-                        tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
-                    }
+            let status = client.connect().await
+                .map_err(|e| anyhow::anyhow!("Failed to connect to Shelby: {}", e))?;
+
+            println!("✅ Connected to Shelby Mesh!");
+            println!("Latency: {}ms", status.latency_ms);
+            println!("Aptos Address: {}", aptos_address);
+            println!("Region: {}", region);
+            println!("Waiting for high-bandwidth streams...");
+
+            // Keep alive
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+            }
+        }
+        Commands::Heartbeat { node } => {
+            let info = cli.chain_client()?.heartbeat(node).await?;
+            println!("Heartbeat recorded for {}", info.owner);
+            println!("Reputation: {}", info.reputation);
+        }
+        Commands::Status { node } => {
+            println!("Checking node status...");
+            match cli.chain_client()?.node_info(node).await? {
+                Some(info) => {
+                    // Unhealthy nodes are skipped for new rooms and rewards until they recover
+                    let status = if info.healthy { "Active" } else { "Unhealthy" };
+                    println!("Status: {}", status);
+                    println!("Last heartbeat: {}", heartbeat_age(info.last_heartbeat));
+                    println!("Address: {}", info.address);
+                    println!("Reputation: {}", info.reputation);
+                    println!("Assigned rooms: {}", info.load);
+                    println!("Accrued rewards: {}", info.rewards);
                 }
-                Err(e) => {
-                    eprintln!("Failed to connect to Shelby: {}", e);
+                None => {
+                    println!("Status: Not registered");
                 }
             }
         }
-        Commands::Status => {
-            println!("Checking node status...");
-            // TODO: Query contract
-            println!("Status: Active");
-            println!("Reputation: 100");
+        Commands::Assignments { node } => {
+            let rooms = cli.chain_client()?.node_assignments(node).await?;
+            if rooms.is_empty() {
+                println!("No rooms assigned to {}", node);
+            } else {
+                println!("Rooms assigned to {}:", node);
+                for room_id in rooms {
+                    println!("  - {}", room_id);
+                }
+            }
         }
-        Commands::Upload { file } => {
+        Commands::Upload { file, room, duration_secs, replay_price } => {
+            // Fail before uploading if the recording cannot be attached afterwards
+            let chain_client = room.as_ref().map(|_| cli.chain_client()).transpose()?;
+            if let (Some(room), Some(chain_client)) = (room, &chain_client) {
+                chain_client.check_recording_target(room, *replay_price).await?;
+            }
+
             println!("Uploading file to Walrus: {}", file);
             let client = walrus::WalrusClient::new();
-            match client.store_file(Path::new(file)).await {
-                Ok(blob_id) => {
-                    println!("Successfully uploaded to Walrus!");
-                    println!("Blob ID: {}", blob_id);
-                }
-                Err(e) => {
-                    eprintln!("Failed to upload file: {}", e);
-                }
+            let blob_id = client.store_file(Path::new(file)).await
+                .map_err(|e| anyhow::anyhow!("Failed to upload file: {}", e))?;
+            println!("Successfully uploaded to Walrus!");
+            println!("Blob ID: {}", blob_id);
+
+            if let (Some(room), Some(chain_client)) = (room, chain_client) {
+                let size_bytes = std::fs::metadata(file).map(|metadata| metadata.len()).unwrap_or_default();
+                chain_client.attach_recording(room, &blob_id, *duration_secs, size_bytes, *replay_price).await?;
+                println!("Recording attached to room {}", room);
             }
         }
     }
+    Ok(())
+}
+
+/// Sends a heartbeat twice per interval so that no interval is missed while the node runs
+async fn send_heartbeats(client: chain::ChillieClient, node: String, interval: Duration) {
+    let mut ticker = tokio::time::interval((interval / 2).max(Duration::from_secs(1)));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = client.heartbeat(&node).await {
+            report_error(&e);
+        }
+    }
+}
+
+/// Describes how long ago a heartbeat given in microseconds since the epoch was recorded
+fn heartbeat_age(last_heartbeat: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as u64);
+    format!("{}s ago", now.saturating_sub(last_heartbeat) / 1_000_000)
+}
+
+/// Prints why a command failed, telling contract rejections apart from connection failures
+fn report_error(error: &anyhow::Error) {
+    match error.downcast_ref::<ChillieError>() {
+        Some(ChillieError::RoomNotFound) => eprintln!("Rejected: the room does not exist on this chain"),
+        Some(ChillieError::RoomHostedElsewhere { chain_id }) => {
+            eprintln!("Rejected: the room runs on chain {}; retry with --chain-id {}", chain_id, chain_id);
+        }
+        Some(rejection) => eprintln!("Rejected: {}", rejection),
+        None => eprintln!("Error: {}", error),
+    }
 }